@@ -1,13 +1,27 @@
+use crate::render::{write_pbm, write_png, Cell, FrameBuffer, Renderer};
 use std::collections::HashMap;
 use std::fs;
 
 pub fn run() {
     let input = fs::read_to_string("day11.txt").unwrap();
     println!("11:1: {:?}", run_1(&input));
-    println!("11:2:\n{:}", run_2(&input));
+    let hull = run_2(&input);
+    match crate::ocr::recognize(&hull) {
+        Ok(text) => println!("11:2: {}", text),
+        Err(e) => println!("11:2:\n{}\n{}", hull, e),
+    }
+
+    // cargo run 11 export
+    if std::env::args().nth(2).as_deref() == Some("export") {
+        let robot = paint(&input, 1);
+        let (width, height, lit) = lit_panels(&robot.floor);
+        write_pbm("day11.pbm", width, height, &lit).unwrap();
+        write_png("day11.png", width, height, &lit, 8).unwrap();
+        fs::write("day11-history.csv", history_csv(&robot.history)).unwrap();
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Direction {
     Left,
     Up,
@@ -44,81 +58,267 @@ impl Direction {
     }
 }
 
-fn run_1(program: &str) -> usize {
-    let mut cpu = crate::intcode::CPU::new(program);
-    let mut current_dir = Direction::Up;
-    let mut current_pos = (0, 0);
-    let mut floor = HashMap::new();
+type Pos = (isize, isize);
+type Floor = HashMap<Pos, i64>;
 
-    loop {
-        let cur_color = floor.entry(current_pos).or_insert(0);
-        let mut input = vec![*cur_color];
-        let res = cpu.run(&mut input);
+trait Brain {
+    // Given the color of the panel under the robot, returns the color to
+    // paint it and where to turn, 0 is left and 1 right. None when done.
+    fn think(&mut self, color: i64) -> Option<(i64, i64)>;
+}
 
-        *cur_color = cpu.output[0];
+struct IntcodeBrain {
+    cpu: crate::intcode::CPU,
+    exited: bool,
+}
 
-        match cpu.output[1] {
-            0 => current_dir = current_dir.left(),
-            1 => current_dir = current_dir.right(),
-            _ => unreachable!(),
+impl IntcodeBrain {
+    fn new(program: &str) -> Self {
+        IntcodeBrain {
+            cpu: crate::intcode::CPU::new(program),
+            exited: false,
+        }
+    }
+}
+
+impl Brain for IntcodeBrain {
+    fn think(&mut self, color: i64) -> Option<(i64, i64)> {
+        if self.exited {
+            return None;
+        }
+
+        let res = self.cpu.run(&mut vec![color]);
+        self.exited = res == crate::intcode::State::Exited;
+
+        let out = match self.cpu.output[..] {
+            [paint, turn] => Some((paint, turn)),
+            _ => None,
         };
-        cpu.output.resize(0, 0);
+        self.cpu.output.clear();
+        out
+    }
+}
 
-        current_pos = current_dir.mv(current_pos);
+// Replays a fixed list of moves, remembering the colors it was shown
+#[cfg(test)]
+struct ScriptedBrain {
+    moves: Vec<(i64, i64)>,
+    seen: Vec<i64>,
+}
 
-        if res == crate::intcode::State::Exited {
-            break;
+#[cfg(test)]
+impl ScriptedBrain {
+    fn new(moves: &[(i64, i64)]) -> Self {
+        ScriptedBrain {
+            moves: moves.to_vec(),
+            seen: Vec::new(),
         }
     }
+}
 
-    floor.len()
+#[cfg(test)]
+impl Brain for ScriptedBrain {
+    fn think(&mut self, color: i64) -> Option<(i64, i64)> {
+        let m = self.moves.get(self.seen.len()).copied();
+        if m.is_some() {
+            self.seen.push(color);
+        }
+        m
+    }
 }
 
-fn run_2(program: &str) -> String {
-    let mut cpu = crate::intcode::CPU::new(program);
-    let mut current_dir = Direction::Up;
-    let mut current_pos = (0, 0);
-    let mut floor = HashMap::new();
+// The panel painted in one step and the direction the robot turned to
+#[derive(Debug, PartialEq)]
+struct Step {
+    pos: Pos,
+    color: i64,
+    dir: Direction,
+}
 
-    // Make start floor white
-    floor.insert((0, 0), 1);
+struct Robot {
+    pos: Pos,
+    dir: Direction,
+    floor: Floor,
+    history: Vec<Step>,
+}
 
-    loop {
-        let cur_color = floor.entry(current_pos).or_insert(0);
-        let mut input = vec![*cur_color];
-        let res = cpu.run(&mut input);
+impl Robot {
+    fn new(start_color: i64) -> Self {
+        let mut floor = HashMap::new();
+        floor.insert((0, 0), start_color);
+        Robot {
+            pos: (0, 0),
+            dir: Direction::Up,
+            floor,
+            history: Vec::new(),
+        }
+    }
 
-        *cur_color = cpu.output[0];
+    fn step(&mut self, color: i64, turn: i64) {
+        self.floor.insert(self.pos, color);
 
-        match cpu.output[1] {
-            0 => current_dir = current_dir.left(),
-            1 => current_dir = current_dir.right(),
+        self.dir = match turn {
+            0 => self.dir.left(),
+            1 => self.dir.right(),
             _ => unreachable!(),
         };
-        cpu.output.resize(0, 0);
 
-        current_pos = current_dir.mv(current_pos);
+        self.history.push(Step {
+            pos: self.pos,
+            color,
+            dir: self.dir,
+        });
 
-        if res == crate::intcode::State::Exited {
-            break;
-        }
+        self.pos = self.dir.mv(self.pos);
     }
 
-    let mut res = String::new();
-    for row in 0..7 {
-        for col in 0..40 {
-            match floor.get(&(col, row)) {
-                Some(c) if *c == 1 => res += "*",
-                _ => res += " ",
+    fn run(&mut self, brain: &mut dyn Brain) {
+        loop {
+            let color = *self.floor.get(&self.pos).unwrap_or(&0);
+            match brain.think(color) {
+                Some((color, turn)) => self.step(color, turn),
+                None => break,
             }
         }
-        res += "\n";
     }
+}
 
+fn paint(program: &str, start_color: i64) -> Robot {
+    let mut robot = Robot::new(start_color);
+    robot.run(&mut IntcodeBrain::new(program));
+    robot
+}
+
+fn run_1(program: &str) -> usize {
+    paint(program, 0).floor.len()
+}
+
+// Smallest and largest coordinates of all visited panels
+fn bounds(floor: &Floor) -> (Pos, Pos) {
+    floor.keys().fold(
+        ((isize::MAX, isize::MAX), (isize::MIN, isize::MIN)),
+        |((min_x, min_y), (max_x, max_y)), (x, y)| {
+            (
+                (min_x.min(*x), min_y.min(*y)),
+                (max_x.max(*x), max_y.max(*y)),
+            )
+        },
+    )
+}
+
+fn render(floor: &Floor) -> FrameBuffer {
+    let ((min_x, min_y), (max_x, max_y)) = bounds(floor);
+    let mut fb = FrameBuffer::new((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize);
+    for ((x, y), c) in floor.iter() {
+        if *c == 1 {
+            fb.set(
+                (x - min_x) as usize,
+                (y - min_y) as usize,
+                Cell::new('*', 0xffffff),
+            );
+        }
+    }
+    fb
+}
+
+// White panels as lit pixels, returns width, height and the pixels
+fn lit_panels(floor: &Floor) -> (usize, usize, Vec<bool>) {
+    let fb = render(floor);
+    let mut lit = Vec::with_capacity(fb.width * fb.height);
+    for y in 0..fb.height {
+        for x in 0..fb.width {
+            lit.push(fb.get(x, y).glyph != ' ');
+        }
+    }
+    (fb.width, fb.height, lit)
+}
+
+fn history_csv(history: &[Step]) -> String {
+    let mut res = String::from("step,x,y,color,direction\n");
+    for (i, s) in history.iter().enumerate() {
+        res += &format!("{},{},{},{},{:?}\n", i, s.pos.0, s.pos.1, s.color, s.dir);
+    }
     res
 }
 
+fn run_2(program: &str) -> String {
+    render(&paint(program, 1).floor).to_string()
+}
+
 #[cfg(test)]
 mod tests {
-    //
+    #[test]
+    fn aoc11_render() {
+        use super::*;
+        let mut floor = HashMap::new();
+        floor.insert((-2, -1), 1);
+        floor.insert((0, 0), 0);
+        floor.insert((1, 1), 1);
+
+        assert_eq!(bounds(&floor), ((-2, -1), (1, 1)));
+        assert_eq!(render(&floor).to_string(), "*   \n    \n   *\n");
+
+        let (width, height, lit) = lit_panels(&floor);
+        assert_eq!((width, height), (4, 3));
+        assert_eq!(lit.iter().filter(|p| **p).count(), 2);
+        assert!(lit[0] && lit[11]);
+    }
+
+    #[test]
+    fn aoc11_history() {
+        use super::*;
+        let history = vec![
+            Step {
+                pos: (0, 0),
+                color: 1,
+                dir: Direction::Left,
+            },
+            Step {
+                pos: (-1, 0),
+                color: 0,
+                dir: Direction::Down,
+            },
+        ];
+        assert_eq!(
+            history_csv(&history),
+            "step,x,y,color,direction\n0,0,0,1,Left\n1,-1,0,0,Down\n"
+        );
+
+        let input = fs::read_to_string("day11.txt").unwrap();
+        let robot = paint(&input, 1);
+        // Every step paints the panel the robot is on
+        assert!(robot
+            .history
+            .iter()
+            .all(|s| robot.floor.contains_key(&s.pos)));
+    }
+
+    #[test]
+    fn aoc11_run_1() {
+        use super::*;
+        // The moves from the puzzle description
+        let mut brain =
+            ScriptedBrain::new(&[(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)]);
+        let mut robot = Robot::new(0);
+        robot.run(&mut brain);
+
+        assert_eq!(brain.seen, vec![0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(robot.floor.len(), 6);
+        assert_eq!(robot.pos, (0, -1));
+        assert_eq!(robot.dir, Direction::Left);
+        assert_eq!(render(&robot.floor).to_string(), "  *\n  *\n** \n");
+
+        let input = fs::read_to_string("day11.txt").unwrap();
+        assert_eq!(run_1(&input), 2441);
+    }
+
+    #[test]
+    fn aoc11_run_2() {
+        use super::*;
+        let input = fs::read_to_string("day11.txt").unwrap();
+        assert_eq!(
+            crate::ocr::recognize(&run_2(&input)),
+            Ok("PZRFPRKC".to_string())
+        );
+    }
 }
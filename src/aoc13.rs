@@ -1,116 +1,606 @@
 use crate::intcode::{State, CPU};
-use minifb::{Window, WindowOptions};
+use crate::render::{
+    to_io, Cell, FrameBuffer, FrameWriter, Renderer, Terminal, WindowRenderer, MULT,
+};
+use std::collections::HashMap;
 use std::fs;
+use std::io;
 
 pub fn run() {
     let input = fs::read_to_string("day13.txt").unwrap();
     println!("13:1 {}", run_1(&input));
     println!("13:2 {}", run_2(&input));
+
+    // cargo run 13 window|terminal|gif|ppm [predict] [scale=N] [skip=N] [score=BOOL]
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let output = args.first().map(|a| a.as_str());
+    let mut strategy: Box<dyn Strategy> = match args.get(1).map(|a| a.as_str()) {
+        Some("predict") => Box::new(PredictTrajectory),
+        _ => Box::new(FollowBall),
+    };
+    let options = args.iter().skip(1).filter(|a| *a != "predict");
+    let options: Vec<String> = options.cloned().collect();
+
+    match output {
+        Some("window") => {
+            let mut window = WindowRenderer::new("Aoc13-2", WIDTH, HEIGHT, MULT);
+            println!("13:2 {}", show(&input, strategy.as_mut(), &mut window));
+        }
+        Some("terminal") => {
+            let mut terminal = Terminal::new(WIDTH, HEIGHT);
+            println!("13:2 {}", show(&input, strategy.as_mut(), &mut terminal));
+        }
+        Some(format) if format == "gif" || format == "ppm" => {
+            let options = match RecordOptions::from_args(&options) {
+                Ok(options) => options,
+                Err(e) => {
+                    println!("13: {}", e);
+                    return;
+                }
+            };
+            let output = match format {
+                "gif" => Output::Gif("day13.gif".to_string()),
+                _ => Output::Ppm("day13-frames".to_string()),
+            };
+            let mut recorder = Recorder::new(options, output);
+            record(&input, strategy.as_mut(), &mut recorder).unwrap();
+            println!("13: {} frames recorded", recorder.written);
+        }
+        _ => (),
+    }
 }
 
-fn run_1(input: &str) -> usize {
-    let mut cpu = CPU::new(input);
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
 
-    let mut input = Vec::new();
+impl Tile {
+    fn from_int(i: i64) -> Self {
+        match i {
+            0 => Self::Empty,
+            1 => Self::Wall,
+            2 => Self::Block,
+            3 => Self::Paddle,
+            4 => Self::Ball,
+            _ => unreachable!(),
+        }
+    }
 
-    let mut blocks = 0;
-    while cpu.run(&mut input) != State::Exited {}
-    for c in cpu.output.chunks(3) {
-        if c[2] == 2 {
-            blocks += 1;
+    fn cell(self) -> Cell {
+        let glyph = match self {
+            Self::Empty => ' ',
+            Self::Wall => '#',
+            Self::Block => '*',
+            Self::Paddle => '-',
+            Self::Ball => 'o',
+        };
+        Cell::new(glyph, color(self as u8))
+    }
+}
+
+type Pos = (i64, i64);
+
+#[derive(Default)]
+struct Board {
+    tiles: HashMap<Pos, Tile>,
+    score: i64,
+    ball: Option<Pos>,
+    // Where the ball was before the last move, gives the direction
+    prev_ball: Option<Pos>,
+    paddle: Pos,
+}
+
+impl Board {
+    // Apply the (x, y, tile) triplets output by the game, returns the tiles
+    // that changed
+    fn update(&mut self, output: &[i64]) -> Vec<(Pos, Tile)> {
+        let mut changed = Vec::new();
+        for c in output.chunks(3) {
+            let pos = (c[0], c[1]);
+            if pos == (-1, 0) {
+                self.score = c[2];
+                continue;
+            }
+
+            let tile = Tile::from_int(c[2]);
+            match tile {
+                Tile::Paddle => self.paddle = pos,
+                Tile::Ball => {
+                    self.prev_ball = self.ball;
+                    self.ball = Some(pos);
+                }
+                _ => (),
+            }
+            self.tiles.insert(pos, tile);
+            changed.push((pos, tile));
         }
+        changed
+    }
+
+    fn tile(&self, pos: Pos) -> Tile {
+        *self.tiles.get(&pos).unwrap_or(&Tile::Empty)
     }
 
-    blocks
+    fn blocks(&self) -> usize {
+        self.tiles.values().filter(|t| **t == Tile::Block).count()
+    }
+}
+
+trait Strategy {
+    // Joystick position, -1 is left, 0 neutral and 1 right
+    fn joystick(&mut self, board: &Board) -> i64;
+}
+
+fn towards(target: i64, paddle: i64) -> i64 {
+    (target - paddle).signum()
+}
+
+struct FollowBall;
+
+impl Strategy for FollowBall {
+    fn joystick(&mut self, board: &Board) -> i64 {
+        board.ball.map_or(0, |b| towards(b.0, board.paddle.0))
+    }
 }
 
-const MULT: usize = 8;
-const WIDTH: usize = 36 * MULT;
-const HEIGHT: usize = 28 * MULT;
+// When the ball is on its way down, follow its diagonal path, bouncing off
+// walls and blocks, to where it will reach the paddle row and go there
+// straight away. On the way up the ball is followed as it will hit blocks
+// that are destroyed, making the prediction unreliable.
+struct PredictTrajectory;
 
-fn draw(video: &mut [u32], x: i64, y: i64, color: u32) {
-    let dx = MULT * (x as usize);
-    let dy = MULT * (y as usize);
-    for _y in dy..(dy + MULT) {
-        for _x in dx..(dx + MULT) {
-            video[_y * WIDTH + _x] = color;
+impl PredictTrajectory {
+    fn landing(board: &Board) -> Option<i64> {
+        let prev = board.prev_ball?;
+        let (mut x, mut y) = board.ball?;
+        let (mut dx, mut dy) = (x - prev.0, y - prev.1);
+        if dx == 0 || dy <= 0 {
+            return None;
         }
+
+        let blocked = |p: Pos| matches!(board.tile(p), Tile::Wall | Tile::Block | Tile::Paddle);
+
+        // Bounded so a ball stuck between blocks can't loop forever
+        for _ in 0..1000 {
+            if dy > 0 && y + 1 == board.paddle.1 {
+                return Some(x);
+            }
+
+            let side = blocked((x + dx, y));
+            let vertical = blocked((x, y + dy));
+            if side {
+                dx = -dx;
+            }
+            if vertical {
+                dy = -dy;
+            }
+            if !side && !vertical && blocked((x + dx, y + dy)) {
+                dx = -dx;
+                dy = -dy;
+            }
+            if !side || !vertical {
+                x += dx;
+                y += dy;
+            }
+        }
+        None
     }
 }
 
-fn run_2(input: &str) -> i64 {
-    let mut cpu = CPU::new(input);
+impl Strategy for PredictTrajectory {
+    fn joystick(&mut self, board: &Board) -> i64 {
+        match Self::landing(board).or_else(|| board.ball.map(|b| b.0)) {
+            Some(target) => towards(target, board.paddle.0),
+            None => 0,
+        }
+    }
+}
+
+// Play the game to the end, `on_frame` is called with the tiles changed on
+// every frame, returns the final score
+fn play(
+    program: &str,
+    strategy: &mut dyn Strategy,
+    mut on_frame: impl FnMut(&Board, &[(Pos, Tile)]) -> bool,
+) -> i64 {
+    let mut cpu = CPU::new(program);
 
     // play for free
     cpu.memory[0] = 2;
 
+    let mut board = Board::default();
     let mut input = Vec::new();
-    let mut score = 0;
 
-    let mut video = vec![0; WIDTH * HEIGHT];
+    loop {
+        let res = cpu.run(&mut input);
+        let changed = board.update(&cpu.output);
+        cpu.output.clear();
+
+        // The last frame is shown too, it has the final score
+        let open = on_frame(&board, &changed);
+        if res == State::Exited || !open {
+            break;
+        }
 
-    let mut window = Window::new("Aoc13-2", WIDTH, HEIGHT, WindowOptions::default()).unwrap();
+        input.push(strategy.joystick(&board));
+    }
 
-    let mut ball_position = 10;
-    let mut paddle_position = 0;
-    while window.is_open() {
-        let res = cpu.run(&mut input);
+    board.score
+}
 
-        for c in cpu.output.chunks(3) {
-            let x = c[0];
-            let y = c[1];
+fn run_1(input: &str) -> usize {
+    let mut cpu = CPU::new(input);
 
-            match c[2] {
-                s if x == -1 && y == 0 => {
-                    score = s;
-                    // println!("Score: {}", c[2]);
-                }
-                0 => draw(&mut video, x, y, 0x01010101),
-                1 => draw(&mut video, x, y, 0xff000000),
-                2 => draw(&mut video, x, y, 0x00ff0000),
-                3 => {
-                    draw(&mut video, x, y, 0xff00ffff);
-                    paddle_position = x;
+    let mut input = Vec::new();
+    while cpu.run(&mut input) != State::Exited {}
+
+    let mut board = Board::default();
+    board.update(&cpu.output);
+    board.blocks()
+}
+
+fn run_2(input: &str) -> i64 {
+    play(input, &mut FollowBall, |_, _| true)
+}
+
+const WIDTH: usize = 36;
+const HEIGHT: usize = 28;
+
+// Play the game drawing every frame, stops early if the display is closed
+fn show(input: &str, strategy: &mut dyn Strategy, renderer: &mut dyn Renderer) -> i64 {
+    play(input, strategy, |_, changed| {
+        for ((x, y), tile) in changed {
+            renderer.set(*x as usize, *y as usize, tile.cell());
+        }
+        renderer.present()
+    })
+}
+
+// 3x5 pixel digits for the score, one row per u8 with the leftmost pixel in
+// bit 2
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+// RGB colors indexed by Tile, used by all the outputs. The last one is used
+// for the score.
+const PALETTE: [u8; 18] = [
+    0x00, 0x00, 0x00, // Empty
+    0x80, 0x80, 0x80, // Wall
+    0xff, 0x00, 0x00, // Block
+    0x00, 0xff, 0xff, // Paddle
+    0xff, 0xff, 0xff, // Ball
+    0xff, 0xff, 0x00, // Score
+];
+const SCORE_COLOR: u8 = 5;
+
+// The 0xRRGGBB color of a palette index
+fn color(index: u8) -> u32 {
+    let i = index as usize * 3;
+    (PALETTE[i] as u32) << 16 | (PALETTE[i + 1] as u32) << 8 | PALETTE[i + 2] as u32
+}
+
+struct RecordOptions {
+    // Pixels per tile, the window uses MULT
+    scale: usize,
+    // Only keep every n:th frame, at least 1
+    skip: usize,
+    // Draw the score below the board
+    score: bool,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            scale: 4,
+            skip: 1,
+            score: true,
+        }
+    }
+}
+
+impl RecordOptions {
+    // The defaults changed by arguments like scale=2, skip=10 or score=false
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = RecordOptions::default();
+        for arg in args {
+            let (key, value) = match arg.find('=') {
+                Some(i) => (&arg[..i], &arg[i + 1..]),
+                None => return Err(format!("expected key=value, got {}", arg)),
+            };
+            let bad = || format!("bad value for {}: {}", key, value);
+            match key {
+                "scale" => options.scale = value.parse().map_err(|_| bad())?,
+                "skip" => options.skip = value.parse().map_err(|_| bad())?,
+                "score" => options.score = value.parse().map_err(|_| bad())?,
+                _ => return Err(format!("unknown option {}", key)),
+            }
+        }
+        if options.scale == 0 || options.skip == 0 {
+            return Err("scale and skip must be at least 1".to_string());
+        }
+        Ok(options)
+    }
+}
+
+// Where the recorded frames are written
+enum Output {
+    // An animated GIF file
+    Gif(String),
+    // A directory of numbered PPM images
+    Ppm(String),
+}
+
+enum Sink {
+    Gif(gif::Encoder<fs::File>),
+    Ppm(FrameWriter),
+}
+
+// Draws the board as palette indices and writes every frame as soon as it is
+// captured, so a whole game never has to fit in memory
+struct Recorder {
+    options: RecordOptions,
+    output: Output,
+    // Opened with the first frame, which gives the size
+    sink: Option<Sink>,
+    width: usize,
+    height: usize,
+    seen: usize,
+    written: usize,
+}
+
+impl Recorder {
+    fn new(options: RecordOptions, output: Output) -> Self {
+        assert!(options.skip > 0, "skip must be at least 1");
+        Recorder {
+            options,
+            output,
+            sink: None,
+            width: 0,
+            height: 0,
+            seen: 0,
+            written: 0,
+        }
+    }
+
+    fn fill(&self, frame: &mut [u8], x: usize, y: usize, color: u8) {
+        let scale = self.options.scale;
+        for _y in (y * scale)..((y + 1) * scale) {
+            for _x in (x * scale)..((x + 1) * scale) {
+                if _x < self.width && _y < self.height {
+                    frame[_y * self.width + _x] = color;
                 }
-                4 => {
-                    draw(&mut video, x, y, 0xffffffff);
-                    ball_position = x;
+            }
+        }
+    }
+
+    // The board as palette indices, one per pixel
+    fn frame(&mut self, board: &Board) -> Vec<u8> {
+        // The first frame has the whole screen, keep that size for the rest
+        if self.width == 0 {
+            let cols = board.tiles.keys().map(|p| p.0).max().unwrap_or(0) + 1;
+            let rows = board.tiles.keys().map(|p| p.1).max().unwrap_or(0) + 1;
+            let score_rows = if self.options.score { 7 } else { 0 };
+            self.width = cols as usize * self.options.scale;
+            self.height = (rows as usize + score_rows) * self.options.scale;
+        }
+
+        let mut frame = vec![Tile::Empty as u8; self.width * self.height];
+        for ((x, y), tile) in &board.tiles {
+            if *x >= 0 && *y >= 0 {
+                self.fill(&mut frame, *x as usize, *y as usize, *tile as u8);
+            }
+        }
+
+        if self.options.score {
+            let top = self.height / self.options.scale - 6;
+            for (i, d) in board.score.to_string().bytes().enumerate() {
+                let digit = DIGITS[(d - b'0') as usize];
+                for (dy, row) in digit.iter().enumerate() {
+                    for dx in 0..3 {
+                        if row & (0b100 >> dx) != 0 {
+                            self.fill(&mut frame, 1 + i * 4 + dx, top + dy, SCORE_COLOR);
+                        }
+                    }
                 }
-                _ => (),
             }
         }
+        frame
+    }
 
-        if res == State::Exited {
-            break;
+    fn open(&self) -> io::Result<Sink> {
+        match &self.output {
+            Output::Gif(path) => {
+                let f = fs::File::create(path)?;
+                let (w, h) = (self.width as u16, self.height as u16);
+                let mut encoder = gif::Encoder::new(f, w, h, &PALETTE).map_err(to_io)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(to_io)?;
+                Ok(Sink::Gif(encoder))
+            }
+            Output::Ppm(dir) => Ok(Sink::Ppm(FrameWriter::new(dir, 1)?)),
         }
+    }
 
-        if ball_position > paddle_position {
-            // move right
-            input.push(1);
-        } else if ball_position < paddle_position {
-            // move left
-            input.push(-1);
-        } else {
-            input.push(0);
+    fn capture(&mut self, board: &Board) -> io::Result<()> {
+        self.seen += 1;
+        if !(self.seen - 1).is_multiple_of(self.options.skip) {
+            return Ok(());
         }
 
-        window.update_with_buffer(&video, WIDTH, HEIGHT).unwrap();
-        cpu.output.clear();
+        let frame = self.frame(board);
+        if self.sink.is_none() {
+            self.sink = Some(self.open()?);
+        }
+        let (width, height) = (self.width, self.height);
+        match self.sink.as_mut().unwrap() {
+            Sink::Gif(encoder) => {
+                let mut frame =
+                    gif::Frame::from_indexed_pixels(width as u16, height as u16, &frame, None);
+                // In hundredths of a second
+                frame.delay = 2;
+                encoder.write_frame(&frame).map_err(to_io)?;
+            }
+            Sink::Ppm(writer) => {
+                let mut fb = FrameBuffer::new(width, height);
+                for (p, c) in frame.iter().enumerate() {
+                    fb.set(p % width, p / width, Cell::new(' ', color(*c)));
+                }
+                writer.write(&fb)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
     }
+}
 
-    score
+// Plays the game, stopping at the first frame that can't be written
+fn record(input: &str, strategy: &mut dyn Strategy, recorder: &mut Recorder) -> io::Result<()> {
+    let mut res = Ok(());
+    play(input, strategy, |board, _| {
+        res = recorder.capture(board);
+        res.is_ok()
+    });
+    res
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn aoc13_board() {
+        let mut board = Board::default();
+        let changed = board.update(&[1, 2, 3, 6, 5, 4, -1, 0, 12345, 2, 2, 2]);
+        assert_eq!(changed.len(), 3);
+        assert_eq!(board.paddle, (1, 2));
+        assert_eq!(board.ball, Some((6, 5)));
+        assert_eq!(board.prev_ball, None);
+        assert_eq!(board.score, 12345);
+        assert_eq!(board.tile((2, 2)), Tile::Block);
+        assert_eq!(board.tile((3, 3)), Tile::Empty);
+        assert_eq!(board.blocks(), 1);
+    }
+
+    #[test]
+    fn aoc13_predict() {
+        let mut board = Board::default();
+        // Walls on both sides, paddle at row 6
+        for y in 0..8 {
+            board.update(&[0, y, 1, 6, y, 1]);
+        }
+        board.update(&[3, 6, 3, 2, 1, 4, 3, 2, 4]);
+        // Moving down right from (3, 2), bounces off the right wall at x = 5
+        assert_eq!(PredictTrajectory::landing(&board), Some(4));
+        assert_eq!(PredictTrajectory.joystick(&board), 1);
+        assert_eq!(FollowBall.joystick(&board), 0);
+    }
 
     #[test]
     fn aoc13_1() {
-        //
+        let input = fs::read_to_string("day13.txt").unwrap();
+        assert_eq!(run_1(&input), 372);
     }
 
     #[test]
     fn aoc13_2() {
-        //
+        let input = fs::read_to_string("day13.txt").unwrap();
+        assert_eq!(run_2(&input), 19297);
+        assert_eq!(play(&input, &mut PredictTrajectory, |_, _| true), 19297);
+    }
+
+    #[test]
+    fn aoc13_show() {
+        use crate::render::FrameBuffer;
+        let input = fs::read_to_string("day13.txt").unwrap();
+        let mut fb = FrameBuffer::new(WIDTH, HEIGHT);
+        assert_eq!(show(&input, &mut FollowBall, &mut fb), 19297);
+        assert!(fb.presented > 0);
+        // All blocks are gone
+        assert!(!fb.to_string().contains('*'));
+        assert_eq!(fb.get(0, 0), Tile::Wall.cell());
+        assert_eq!(Tile::Wall.cell().color, 0x808080);
+    }
+
+    #[test]
+    fn aoc13_record() {
+        let mut board = Board::default();
+        board.update(&[0, 0, 1, 1, 0, 1, 0, 1, 3, 1, 1, 4, -1, 0, 7]);
+
+        let dir = std::env::temp_dir().join("aoc13_record");
+        let dir = dir.to_str().unwrap().to_string();
+        let options = RecordOptions {
+            scale: 2,
+            skip: 2,
+            score: false,
+        };
+        let mut recorder = Recorder::new(options, Output::Ppm(dir.clone()));
+        for _ in 0..3 {
+            recorder.capture(&board).unwrap();
+        }
+        assert_eq!(recorder.written, 2);
+        assert_eq!((recorder.width, recorder.height), (4, 4));
+        assert_eq!(
+            recorder.frame(&board),
+            vec![1, 1, 1, 1, 1, 1, 1, 1, 3, 3, 4, 4, 3, 3, 4, 4]
+        );
+        let ppm = fs::read(std::path::Path::new(&dir).join("frame-0001.ppm")).unwrap();
+        assert!(ppm.starts_with(b"P6\n4 4\n255\n"));
+        assert_eq!(ppm.len(), 11 + 4 * 4 * 3);
+
+        let gif = std::path::Path::new(&dir).join("game.gif");
+        let options = RecordOptions {
+            scale: 1,
+            skip: 1,
+            score: true,
+        };
+        let output = Output::Gif(gif.to_str().unwrap().to_string());
+        let mut recorder = Recorder::new(options, output);
+        recorder.capture(&board).unwrap();
+        assert_eq!((recorder.width, recorder.height), (2, 9));
+        // The 7 is drawn from x = 1, its top row is solid
+        assert_eq!(recorder.frame(&board)[3 * 2 + 1], SCORE_COLOR);
+        // Finishes the file
+        drop(recorder);
+        assert!(fs::read(gif).unwrap().starts_with(b"GIF89a"));
+    }
+
+    #[test]
+    fn aoc13_record_options() {
+        let args = |a: &[&str]| -> Vec<String> { a.iter().map(|s| s.to_string()).collect() };
+        let options = RecordOptions::from_args(&args(&["scale=2", "skip=10", "score=false"]));
+        let options = options.unwrap();
+        assert_eq!((options.scale, options.skip, options.score), (2, 10, false));
+        let options = RecordOptions::from_args(&[]).unwrap();
+        assert_eq!((options.scale, options.skip, options.score), (4, 1, true));
+
+        assert!(RecordOptions::from_args(&args(&["skip=0"])).is_err());
+        assert!(RecordOptions::from_args(&args(&["scale=x"])).is_err());
+        assert!(RecordOptions::from_args(&args(&["speed=2"])).is_err());
+        assert!(RecordOptions::from_args(&args(&["scale"])).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "skip must be at least 1")]
+    fn aoc13_record_skip() {
+        let options = RecordOptions {
+            scale: 1,
+            skip: 0,
+            score: false,
+        };
+        Recorder::new(options, Output::Ppm("day13-frames".to_string()));
     }
 }
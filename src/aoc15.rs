@@ -1,15 +1,51 @@
-use pathfinding::prelude::astar;
+use crate::intcode::CPU;
+use crate::render::{
+    write_frames, write_ppm, Cell, FrameBuffer, Renderer, Terminal, WindowRenderer, FRAME_DELAY,
+    MULT,
+};
+use rayon::prelude::*;
 use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::io;
 
 pub fn run() {
     let input = fs::read_to_string("day15.txt").unwrap();
     println!("15:1 {}", run_1(&input));
     // 351 too high
     println!("15:2 {}", run_2(&input));
+
+    // cargo run 15 map|terminal|window|frames|heat [sequential]
+    let mode = std::env::args().nth(2);
+    if mode.is_none() {
+        return;
+    }
+    let parallel = std::env::args().nth(3).as_deref() != Some("sequential");
+    let maze = explore(CPU::new(&input), parallel);
+    let (width, height) = size(&maze.map);
+    match mode.as_deref() {
+        Some("map") => {
+            let mut terminal = Terminal::plain(width, height);
+            draw_map(&maze.map, maze.oxy_pos, &mut terminal);
+            terminal.present();
+        }
+        Some("terminal") => animate(&maze, &mut Terminal::new(width, height)),
+        Some("window") => animate(
+            &maze,
+            &mut WindowRenderer::new("Aoc15-2", width, height, MULT),
+        ),
+        Some("frames") => {
+            spread_frames(&maze, "day15-frames").unwrap();
+        }
+        Some("heat") => {
+            let heat = heat_map(&maze);
+            write_ppm("day15-heat.ppm", &heat, MULT).unwrap();
+            fs::write("day15-heat.txt", heat.to_string()).unwrap();
+        }
+        _ => (),
+    }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum Direction {
     North,
     South,
@@ -17,8 +53,15 @@ enum Direction {
     East,
 }
 
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::West,
+    Direction::East,
+];
+
 impl Direction {
-    fn to_opcode(&self) -> i64 {
+    fn to_opcode(self) -> i64 {
         match self {
             Self::North => 1,
             Self::South => 2,
@@ -27,13 +70,12 @@ impl Direction {
         }
     }
 
-    fn to_direction((from_x, from_y): Coord, (to_x, to_y): Coord) -> Self {
-        match (to_x - from_x, to_y - from_y) {
-            (-1, 0) => Self::West,
-            (1, 0) => Self::East,
-            (0, -1) => Self::North,
-            (0, 1) => Self::South,
-            _ => unreachable!(),
+    fn mv(&self, (x, y): Coord) -> Coord {
+        match self {
+            Self::North => (x, y - 1),
+            Self::South => (x, y + 1),
+            Self::West => (x - 1, y),
+            Self::East => (x + 1, y),
         }
     }
 }
@@ -47,167 +89,457 @@ enum MoveResult {
 
 type Coord = (i64, i64);
 
-fn neighbors((x, y): Coord, visited: &HashSet<Coord>) -> Vec<Coord> {
-    vec![(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
-        .into_iter()
-        .filter(|c| !visited.contains(c))
-        .collect()
+// Something that can be told to move one step, cloned to explore several
+// paths at once
+trait Droid: Clone + Send {
+    fn do_move(&mut self, dir: Direction) -> MoveResult;
 }
 
-fn do_move(cpu: &mut crate::intcode::CPU, from: Coord, to: Coord) -> MoveResult {
-    let dir = Direction::to_direction(from, to);
-    let mut input = vec![dir.to_opcode()];
-    cpu.run(&mut input);
+impl Droid for CPU {
+    fn do_move(&mut self, dir: Direction) -> MoveResult {
+        let mut input = vec![dir.to_opcode()];
+        self.run(&mut input);
 
-    match cpu.output.pop() {
-        Some(0) => MoveResult::HitWall,
-        Some(1) => MoveResult::Moved,
-        Some(2) => MoveResult::MovedAndOxygen,
-        _ => unreachable!(),
+        match self.output.pop() {
+            Some(0) => MoveResult::HitWall,
+            Some(1) => MoveResult::Moved,
+            Some(2) => MoveResult::MovedAndOxygen,
+            _ => unreachable!(),
+        }
     }
 }
 
-fn build_map(program: &str) -> (HashMap<Coord, bool>, Coord) {
-    let mut cpu = crate::intcode::CPU::new(program);
-    let mut cur = (0, 0);
-    let mut oxy_pos = None;
+// A droid in a maze drawn with '#' for walls, '.' for open cells, 'D' for the
+// start and 'O' for the oxygen system. Everything outside of the rows is wall.
+// The maze needs a 'D' to start from, GridDroid::new panics without one.
+#[cfg(test)]
+#[derive(Clone)]
+struct GridDroid {
+    grid: Vec<Vec<char>>,
+    pos: Coord,
+}
+
+#[cfg(test)]
+impl GridDroid {
+    fn new(maze: &str) -> Self {
+        let grid: Vec<Vec<char>> = maze.lines().map(|l| l.chars().collect()).collect();
+        if let Some(c) = grid.iter().flatten().find(|c| !"#.DO".contains(**c)) {
+            panic!("unexpected {:?} in the maze", c);
+        }
+        let pos = grid
+            .iter()
+            .enumerate()
+            .find_map(|(y, row)| {
+                row.iter()
+                    .position(|c| *c == 'D')
+                    .map(|x| (x as i64, y as i64))
+            })
+            .expect("no 'D' in the maze");
+        GridDroid { grid, pos }
+    }
+
+    fn at(&self, (x, y): Coord) -> char {
+        if x < 0 || y < 0 {
+            return '#';
+        }
+        *self
+            .grid
+            .get(y as usize)
+            .and_then(|row| row.get(x as usize))
+            .unwrap_or(&'#')
+    }
+}
+
+#[cfg(test)]
+impl Droid for GridDroid {
+    fn do_move(&mut self, dir: Direction) -> MoveResult {
+        let to = dir.mv(self.pos);
+        match self.at(to) {
+            '#' => MoveResult::HitWall,
+            c => {
+                self.pos = to;
+                match c {
+                    'O' => MoveResult::MovedAndOxygen,
+                    _ => MoveResult::Moved,
+                }
+            }
+        }
+    }
+}
+
+struct Maze {
+    // true for open cells, false for walls
+    map: HashMap<Coord, bool>,
+    // Number of steps from the start to every open cell
+    dist: HashMap<Coord, usize>,
+    oxy_pos: Coord,
+}
 
+fn try_move<D: Droid>((to, dir, mut droid): (Coord, Direction, D)) -> (Coord, MoveResult, D) {
+    let res = droid.do_move(dir);
+    (to, res, droid)
+}
+
+// Explores the maze breadth first. Every open cell on the frontier keeps a
+// droid standing on it, to try a neighbor the droid is cloned and moved one
+// step, so there's never any need to walk back. The moves for a step can be
+// tried in parallel, or one at a time in a fixed order.
+fn explore<D: Droid>(droid: D, parallel: bool) -> Maze {
+    let start = (0, 0);
     let mut map = HashMap::new();
-    let mut visited = HashSet::new();
-    let mut path = Vec::new();
+    let mut dist = HashMap::new();
+    let mut oxy_pos = None;
+    map.insert(start, true);
+    dist.insert(start, 0);
 
-    loop {
-        visited.insert(cur);
-        map.insert(cur, true);
-        let nbrs = neighbors(cur, &visited);
+    let mut frontier = vec![(start, droid)];
+    let mut steps = 0;
 
-        // Try to get the first neighbor
+    while !frontier.is_empty() {
+        steps += 1;
 
-        match nbrs.iter().next() {
-            Some(to) => match do_move(&mut cpu, cur, *to) {
-                MoveResult::HitWall => {
-                    visited.insert(*to);
-                    map.insert(*to, false);
+        // Unexplored neighbors, only tried once even if next to several
+        // frontier cells
+        let mut tried = HashSet::new();
+        let mut moves = Vec::new();
+        for (pos, droid) in frontier.iter() {
+            for dir in DIRECTIONS.iter() {
+                let to = dir.mv(*pos);
+                if !map.contains_key(&to) && tried.insert(to) {
+                    moves.push((to, *dir, droid.clone()));
                 }
-                r => {
-                    if r == MoveResult::MovedAndOxygen {
-                        oxy_pos = Some(*to);
-                    }
+            }
+        }
 
-                    path.push(cur);
-                    cur = *to;
-                }
-            },
-            None => {
-                // backtrack to nearest to_explore
-                match path.pop() {
-                    // Go back one step in the path
-                    Some(to) => {
-                        // We ignore the result here as we're moving back
-                        // println!("Backtrack from {:?} to {:?}", cur, to);
-                        do_move(&mut cpu, cur, to);
-                        cur = to;
-                    }
-                    // If path is empty, we've exhausted all search options and the map should be
-                    // complete
-                    None => break,
-                }
+        let results: Vec<_> = match parallel {
+            true => moves.into_par_iter().map(try_move).collect(),
+            false => moves.into_iter().map(try_move).collect(),
+        };
+
+        frontier = Vec::new();
+        for (to, res, droid) in results {
+            if res == MoveResult::HitWall {
+                map.insert(to, false);
+                continue;
+            }
+
+            if res == MoveResult::MovedAndOxygen {
+                oxy_pos = Some(to);
             }
+            map.insert(to, true);
+            dist.insert(to, steps);
+            frontier.push((to, droid));
         }
     }
 
-    (map, oxy_pos.unwrap())
+    Maze {
+        map,
+        dist,
+        oxy_pos: oxy_pos.unwrap(),
+    }
 }
 
-fn run_1(program: &str) -> usize {
-    let (map, oxy_pos) = build_map(program);
-
-    let (min_x, min_y, max_x, max_y) =
-        map.keys()
-            .fold((0, 0, 0, 0), |(min_x, min_y, max_x, max_y), (x, y)| {
-                (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
-            });
+fn bounds(map: &HashMap<Coord, bool>) -> (i64, i64, i64, i64) {
+    map.keys()
+        .fold((0, 0, 0, 0), |(min_x, min_y, max_x, max_y), (x, y)| {
+            (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
+        })
+}
 
+fn draw_map(map: &HashMap<Coord, bool>, oxy_pos: Coord, renderer: &mut dyn Renderer) {
+    let (min_x, min_y, max_x, max_y) = bounds(map);
     let start = (0, 0);
 
-    // Print map
     let f = false;
-    for y in min_y..max_y {
-        for x in min_x..max_x {
-            if *map.get(&(x, y)).unwrap_or(&f) {
-                if oxy_pos == (x, y) {
-                    print!("O");
-                } else if start == (x, y) {
-                    print!("S");
-                } else {
-                    print!(".");
-                }
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let cell = if !*map.get(&(x, y)).unwrap_or(&f) {
+                Cell::new('#', 0x808080)
+            } else if oxy_pos == (x, y) {
+                Cell::new('O', 0x0000ff)
+            } else if start == (x, y) {
+                Cell::new('S', 0x00ff00)
             } else {
-                print!("#");
-            }
+                Cell::new('.', 0xffffff)
+            };
+            renderer.set((x - min_x) as usize, (y - min_y) as usize, cell);
         }
-        println!("");
     }
+}
 
-    // Find the shortest path from start to oxy_pos
-    let shortest_path = astar(
-        &start,
-        |(x, y)| {
-            vec![(*x - 1, *y), (*x + 1, *y), (*x, *y - 1), (*x, *y + 1)]
-                .into_iter()
-                .filter(|c| *map.get(c).unwrap())
-                .map(|c| (c, 1))
-                .collect::<Vec<(Coord, i64)>>()
-        },
-        |(x, y)| (x - oxy_pos.0).abs() + (y - oxy_pos.1).abs(),
-        |c| *c == oxy_pos,
-    );
-    // Number of steps are the elements in shortest_path - 1 (since start is included)
-    shortest_path.unwrap().0.len() - 1
+// Screen size of the explored map
+fn size(map: &HashMap<Coord, bool>) -> (usize, usize) {
+    let (min_x, min_y, max_x, max_y) = bounds(map);
+    ((max_x - min_x + 1) as usize, (max_y - min_y + 1) as usize)
 }
 
-fn run_2(program: &str) -> usize {
-    let (map, oxy_pos) = build_map(program);
+// The shortest path from the start to `target`, both included
+fn path_to(maze: &Maze, target: Coord) -> Vec<Coord> {
+    let mut path = vec![target];
+    let mut cur = target;
+    while maze.dist[&cur] > 0 {
+        let prev = maze.dist[&cur] - 1;
+        cur = DIRECTIONS
+            .iter()
+            .map(|d| d.mv(cur))
+            .find(|c| maze.dist.get(c) == Some(&prev))
+            .unwrap();
+        path.push(cur);
+    }
+    path.reverse();
+    path
+}
 
-    let mut oxygen_filled = HashSet::new();
-    oxygen_filled.insert(oxy_pos);
+// The minute the oxygen reaches every open cell, breadth first from the
+// oxygen system
+fn spread(map: &HashMap<Coord, bool>, oxy_pos: Coord) -> HashMap<Coord, usize> {
+    let mut minutes = HashMap::new();
+    minutes.insert(oxy_pos, 0);
 
-    let mut neighbors = HashSet::new();
-    neighbors.insert(oxy_pos);
+    let mut front = vec![oxy_pos];
+    let mut minute = 0;
+    while !front.is_empty() {
+        minute += 1;
+        let mut new_front = Vec::new();
+        for pos in front {
+            for dir in DIRECTIONS.iter() {
+                let to = dir.mv(pos);
+                if map[&to] && !minutes.contains_key(&to) {
+                    minutes.insert(to, minute);
+                    new_front.push(to);
+                }
+            }
+        }
+        front = new_front;
+    }
 
-    let mut minutes = 0;
+    minutes
+}
 
-    while !neighbors.is_empty() {
-        let mut new_nbrs = HashSet::new();
-        for (x, y) in neighbors.iter() {
-            // Make sure we don't consider this again
-            oxygen_filled.insert((*x, *y));
+// The map with the droid path to the oxygen system and the oxygen that has
+// spread after `minute` minutes, the newly filled cells are the front
+fn draw_spread(
+    maze: &Maze,
+    oxygen: &HashMap<Coord, usize>,
+    minute: usize,
+    renderer: &mut dyn Renderer,
+) {
+    draw_map(&maze.map, maze.oxy_pos, renderer);
 
-            vec![(*x - 1, *y), (*x + 1, *y), (*x, *y - 1), (*x, *y + 1)]
-                .into_iter()
-                .filter(|c| *map.get(c).unwrap())
-                .filter(|c| !oxygen_filled.contains(c))
-                .collect::<Vec<_>>()
-                .iter()
-                .for_each(|c| {
-                    new_nbrs.insert(*c);
-                });
+    let (min_x, min_y, _, _) = bounds(&maze.map);
+    let mut set =
+        |(x, y): Coord, cell| renderer.set((x - min_x) as usize, (y - min_y) as usize, cell);
+    // Leaves out both ends, a path of one or two cells has nothing between
+    let path = path_to(maze, maze.oxy_pos);
+    for pos in path.get(1..path.len().saturating_sub(1)).unwrap_or(&[]) {
+        set(*pos, Cell::new('+', 0xffff00));
+    }
+    for (pos, m) in oxygen.iter() {
+        if *pos == maze.oxy_pos || *m > minute {
+            continue;
+        }
+        match *m == minute {
+            true => set(*pos, Cell::new('*', 0x00ffff)),
+            false => set(*pos, Cell::new('~', 0x0000ff)),
         }
+    }
+}
 
-        neighbors = new_nbrs;
-        if !neighbors.is_empty() {
-            minutes += 1;
+fn animate(maze: &Maze, renderer: &mut dyn Renderer) {
+    let oxygen = spread(&maze.map, maze.oxy_pos);
+    let minutes = *oxygen.values().max().unwrap();
+    for minute in 0..=minutes {
+        draw_spread(maze, &oxygen, minute, renderer);
+        if !renderer.present() {
+            break;
         }
+        std::thread::sleep(FRAME_DELAY);
     }
+}
 
-    minutes
+// One PPM per minute
+fn spread_frames(maze: &Maze, dir: &str) -> io::Result<usize> {
+    let (width, height) = size(&maze.map);
+    let oxygen = spread(&maze.map, maze.oxy_pos);
+    let minutes = *oxygen.values().max().unwrap();
+
+    let frames = (0..=minutes).map(|minute| {
+        let mut fb = FrameBuffer::new(width, height);
+        draw_spread(maze, &oxygen, minute, &mut fb);
+        fb
+    });
+    write_frames(dir, frames, MULT)
+}
+
+// From red next to the oxygen system to blue furthest away
+fn heat_color(minute: usize, max: usize) -> u32 {
+    let t = (minute * 255 / max.max(1)) as u32;
+    ((255 - t) << 16) | t
+}
+
+// The distance from the oxygen system to every open cell, the glyph is the
+// last digit of the distance
+fn heat_map(maze: &Maze) -> FrameBuffer {
+    let (min_x, min_y, _, _) = bounds(&maze.map);
+    let (width, height) = size(&maze.map);
+    let oxygen = spread(&maze.map, maze.oxy_pos);
+    let max = *oxygen.values().max().unwrap();
+
+    let mut fb = FrameBuffer::new(width, height);
+    for ((x, y), open) in maze.map.iter() {
+        let cell = match oxygen.get(&(*x, *y)) {
+            Some(m) if *open => Cell::new(
+                std::char::from_digit((*m % 10) as u32, 10).unwrap(),
+                heat_color(*m, max),
+            ),
+            _ => Cell::new('#', 0x202020),
+        };
+        fb.set((x - min_x) as usize, (y - min_y) as usize, cell);
+    }
+    fb
+}
+
+fn run_1(program: &str) -> usize {
+    let maze = explore(CPU::new(program), true);
+    maze.dist[&maze.oxy_pos]
+}
+
+fn run_2(program: &str) -> usize {
+    let Maze { map, oxy_pos, .. } = explore(CPU::new(program), true);
+    *spread(&map, oxy_pos).values().max().unwrap()
 }
 
 #[cfg(test)]
 mod tests {
+    #[test]
+    fn aoc15_explore() {
+        use super::*;
+        let input = fs::read_to_string("day15.txt").unwrap();
+        let maze = explore(CPU::new(&input), true);
+
+        // Every open cell has all its neighbors explored
+        for (pos, _) in maze.map.iter().filter(|(_, open)| **open) {
+            assert!(maze.dist.contains_key(pos));
+            assert!(DIRECTIONS
+                .iter()
+                .all(|d| maze.map.contains_key(&d.mv(*pos))));
+        }
+        assert_eq!(maze.dist[&maze.oxy_pos], 252);
+
+        // One move at a time finds the same maze
+        let sequential = explore(CPU::new(&input), false);
+        assert_eq!(sequential.map, maze.map);
+        assert_eq!(sequential.dist, maze.dist);
+        assert_eq!(sequential.oxy_pos, maze.oxy_pos);
+    }
+
+    // The example from part 2 with a start added and the unknown cells as walls
+    const EXAMPLE: &str = "###
+#D.##
+#.#..#
+#.O.#
+####";
+
+    #[test]
+    fn aoc15_grid_droid() {
+        use super::*;
+        let mut droid = GridDroid::new(EXAMPLE);
+        assert!(droid.do_move(Direction::North) == MoveResult::HitWall);
+        assert!(droid.do_move(Direction::South) == MoveResult::Moved);
+        assert!(droid.do_move(Direction::South) == MoveResult::Moved);
+        assert!(droid.do_move(Direction::East) == MoveResult::MovedAndOxygen);
+        assert_eq!(droid.pos, (2, 3));
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected ' ' in the maze")]
+    fn aoc15_grid_droid_padding() {
+        use super::*;
+        GridDroid::new("#####\n#D.O#\n ####");
+    }
+
+    #[test]
+    #[should_panic(expected = "no 'D' in the maze")]
+    fn aoc15_grid_droid_start() {
+        use super::*;
+        GridDroid::new("#####\n#..O#\n#####");
+    }
+
+    #[test]
+    fn aoc15_short_spread() {
+        use super::*;
+        // The oxygen system right at the start, the path is a single cell
+        let maze = Maze {
+            map: vec![(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)]
+                .into_iter()
+                .map(|pos| (pos, pos == (0, 0)))
+                .collect(),
+            dist: vec![((0, 0), 0)].into_iter().collect(),
+            oxy_pos: (0, 0),
+        };
+        let oxygen = spread(&maze.map, maze.oxy_pos);
+        let mut fb = FrameBuffer::new(3, 3);
+        draw_spread(&maze, &oxygen, 0, &mut fb);
+        assert!(!fb.to_string().contains('+'));
+    }
+
+    #[test]
+    fn aoc15_run_1() {
+        use super::*;
+        let maze = explore(GridDroid::new(EXAMPLE), true);
+        assert_eq!(maze.oxy_pos, (1, 2));
+        assert_eq!(maze.dist[&maze.oxy_pos], 3);
+        assert_eq!(
+            path_to(&maze, maze.oxy_pos),
+            vec![(0, 0), (0, 1), (0, 2), (1, 2)]
+        );
+
+        let (width, height) = size(&maze.map);
+        let mut fb = FrameBuffer::new(width, height);
+        draw_map(&maze.map, maze.oxy_pos, &mut fb);
+        assert_eq!(fb.to_string(), "######\n#S.###\n#.#..#\n#.O.##\n######\n");
+    }
 
     #[test]
     fn aoc15_run_2() {
-        // let map =
+        use super::*;
+        let maze = explore(GridDroid::new(EXAMPLE), true);
+        let oxygen = spread(&maze.map, maze.oxy_pos);
+        assert_eq!(*oxygen.values().max().unwrap(), 4);
+        assert_eq!(oxygen[&(1, 0)], 4);
+
+        let input = fs::read_to_string("day15.txt").unwrap();
+        assert_eq!(run_2(&input), 350);
+    }
+
+    #[test]
+    fn aoc15_spread() {
+        use super::*;
+        let input = fs::read_to_string("day15.txt").unwrap();
+        let maze = explore(CPU::new(&input), true);
+
+        let path = path_to(&maze, maze.oxy_pos);
+        assert_eq!(path.len(), 253);
+        assert_eq!(path[0], (0, 0));
+
+        let oxygen = spread(&maze.map, maze.oxy_pos);
+        let (width, height) = size(&maze.map);
+        let mut fb = FrameBuffer::new(width, height);
+        draw_spread(&maze, &oxygen, 1, &mut fb);
+        let text = fb.to_string();
+        assert_eq!(text.matches('O').count(), 1);
+        assert_eq!(text.matches('S').count(), 1);
+        // The last step onto the oxygen system is already filled
+        assert_eq!(text.matches('+').count(), 250);
+        assert!(text.matches('*').count() >= 1);
+        assert_eq!(text.matches('~').count(), 0);
+
+        let heat = heat_map(&maze).to_string();
+        assert_eq!(
+            heat.matches(|c: char| c.is_ascii_digit()).count(),
+            maze.dist.len()
+        );
+        assert_eq!(heat_color(0, 350), 0xff0000);
+        assert_eq!(heat_color(350, 350), 0x0000ff);
     }
 }
@@ -1,5 +1,4 @@
 use rayon::prelude::*;
-use std::collections::HashMap;
 use std::fs;
 
 use nom::{bytes::complete::take_while1, character::is_digit, combinator::map, IResult};
@@ -7,8 +6,25 @@ use nom::{bytes::complete::take_while1, character::is_digit, combinator::map, IR
 pub fn run() {
     let input = fs::read_to_string("day16.txt").unwrap();
 
-    println!("16:1 - {:?}", run_1(&input, 100, 8));
-    println!("16:2 - {:?}", run_2(&input, 100, 8));
+    // cargo run 16 [base pattern like 0,1,0,-1] [sequential]
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let parallel = !args.iter().any(|a| a == "sequential");
+    let base = match args.iter().find(|a| *a != "sequential") {
+        Some(arg) => match arg.split(',').map(|v| v.parse()).collect() {
+            Ok(base) => base,
+            Err(e) => {
+                println!("16: bad base pattern {}, {}", arg, e);
+                return;
+            }
+        },
+        None => BASE_PATTERN.to_vec(),
+    };
+
+    println!("16:1 - {:?}", run_1(&input, 100, 8, &base, parallel));
+    match run_2(&input, 100, 8) {
+        Ok(message) => println!("16:2 - {:?}", message),
+        Err(e) => println!("16:2 failed, {}", e),
+    }
 }
 
 fn is_char_digit(chr: char) -> bool {
@@ -27,29 +43,38 @@ fn parse(i: &str) -> IResult<&str, Vec<isize>> {
 
 const BASE_PATTERN: [isize; 4] = [0, 1, 0, -1];
 
-fn pattern_for_pos(pos: usize, input_len: usize) -> Vec<isize> {
-    let mut res = Vec::with_capacity(pos * BASE_PATTERN.len());
-
-    let mut first = true;
-    while res.len() < input_len {
-        for v in BASE_PATTERN.iter() {
-            for _ in 0..pos {
-                if first {
-                    first = false;
-                } else {
-                    res.push(*v);
-                }
-            }
-        }
+// One output digit of a phase with any base pattern. The pattern for output
+// position i repeats every base value i + 1 times and skips the very first
+// value, so the input is split into blocks that all get the same multiplier
+// and the sum of each block comes from the prefix sums.
+fn phase_digit(prefix: &[isize], base: &[isize], i: usize) -> isize {
+    let len = prefix.len() - 1;
+    let run = i + 1;
+    let mut sum = 0;
+    let mut block = 0;
+    // The first block is one shorter because of the skipped value
+    while block * run < len + 1 {
+        let start = (block * run).saturating_sub(1);
+        let end = ((block + 1) * run - 1).min(len);
+        sum += base[block % base.len()] * (prefix[end] - prefix[start]);
+        block += 1;
     }
-
-    // res.into_iter().skip(1).take(input_len).collect()
-    res.resize(input_len, 0);
-    res
+    (sum % 10).abs()
 }
 
-fn pattern(pos: usize, input_len: usize, lookup: &mut HashMap<usize, Vec<isize>>) -> &[isize] {
-    lookup.entry(pos).or_insert(pattern_for_pos(pos, input_len))
+// One phase, optionally computing the output digits in parallel
+fn phase(input: &[isize], base: &[isize], parallel: bool) -> Vec<isize> {
+    let mut prefix = Vec::with_capacity(input.len() + 1);
+    prefix.push(0);
+    for d in input {
+        prefix.push(prefix[prefix.len() - 1] + d);
+    }
+
+    let digit = |i| phase_digit(&prefix, base, i);
+    match parallel {
+        true => (0..input.len()).into_par_iter().map(digit).collect(),
+        false => (0..input.len()).map(digit).collect(),
+    }
 }
 
 fn get_index(data: &[isize]) -> usize {
@@ -62,62 +87,63 @@ fn get_index(data: &[isize]) -> usize {
     idx
 }
 
-fn run_1(input: &str, iterations: usize, digits: usize) -> Vec<isize> {
+fn run_1(
+    input: &str,
+    iterations: usize,
+    digits: usize,
+    base: &[isize],
+    parallel: bool,
+) -> Vec<isize> {
     let (_, mut input) = parse(input).unwrap();
 
-    let mut lookup = HashMap::new();
-
     for _ in 0..iterations {
-        let new_input = input
-            .iter()
-            .enumerate()
-            .map(|(pos, _)| {
-                let p = pattern(pos + 1, input.len(), &mut lookup).iter();
-                let muls = input.iter().zip(p).map(|(a, b)| a * b);
-                let sum = muls.sum::<isize>().abs();
-                sum % 10
-            })
-            .collect();
-
-        input = new_input;
+        input = phase(&input, base, parallel);
     }
     input.truncate(digits);
     input
 }
 
-fn run_2(input: &str, _iterations: usize, digits: usize) -> Vec<isize> {
-    let (_, mut input) = parse(input).unwrap();
+// How many times the signal is repeated in part 2
+const REPEAT: usize = 10000;
+
+// Past the middle of the signal the pattern for a position is all 0 before
+// it and all 1 from it, so every new digit is the sum of the digit and all
+// digits after it. When the message is in the second half only the tail
+// from the message offset is needed, otherwise every phase is done in full.
+fn run_2(input: &str, iterations: usize, digits: usize) -> Result<Vec<isize>, String> {
+    let (_, input) = parse(input).unwrap();
 
     let message_offset = get_index(&input);
+    let len = input.len() * REPEAT;
+    if message_offset + digits > len {
+        return Err(format!(
+            "message offset {} is past the end of {}",
+            message_offset, len
+        ));
+    }
 
-    {
-        let orig = input.clone();
-        for _ in 0..10000 {
-            input.extend(&orig);
+    if message_offset < len / 2 {
+        let mut signal: Vec<isize> = (0..len).map(|i| input[i % input.len()]).collect();
+        for _ in 0..iterations {
+            signal = phase(&signal, &BASE_PATTERN, true);
         }
+        return Ok(signal[message_offset..message_offset + digits].to_vec());
     }
 
-    let mut lookup = Vec::with_capacity(input.len());
-    (0..input.len())
-        .into_par_iter()
-        .map(|i| pattern_for_pos(i + 1, input.len()))
-        .collect_into_vec(&mut lookup);
-    // for i in 0..input.len() {
-    //     lookup.push(pattern_for_pos(i + 1, lookup.len()));
-    // }
-    // let lookup: Vec<Vec<isize>> = input
-    //     .iter()
-    //     .cycle()
-    //     .enumerate()
-    //     .par_map(|(pos, _)| pattern_for_pos(pos, input_len))
-    //     .collect();
+    let mut tail: Vec<isize> = (message_offset..len)
+        .map(|i| input[i % input.len()])
+        .collect();
 
-    input
-        .iter()
-        .skip(message_offset)
-        .take(digits)
-        .map(|i| *i)
-        .collect()
+    for _ in 0..iterations {
+        let mut sum = 0;
+        for d in tail.iter_mut().rev() {
+            sum = (sum + *d) % 10;
+            *d = sum;
+        }
+    }
+
+    tail.truncate(digits);
+    Ok(tail)
 }
 
 #[cfg(test)]
@@ -128,50 +154,83 @@ mod tests {
         assert_eq!(parse("15243"), Ok(("", vec![1, 5, 2, 4, 3])));
     }
 
+    // The pattern for a position written out, to check against
+    fn pattern_for_pos(base: &[isize], pos: usize, input_len: usize) -> Vec<isize> {
+        let mut res = Vec::with_capacity(input_len + 1);
+
+        while res.len() <= input_len {
+            for v in base.iter() {
+                for _ in 0..pos {
+                    res.push(*v);
+                }
+            }
+        }
+
+        res.into_iter().skip(1).take(input_len).collect()
+    }
+
     #[test]
     fn aoc16_pattern() {
         use super::*;
-        assert_eq!(pattern_for_pos(1, 3), vec![1, 0, -1]);
-        assert_eq!(pattern_for_pos(2, 7), vec![0, 1, 1, 0, 0, -1, -1]);
-        assert_eq!(
-            pattern_for_pos(3, 11),
-            vec![0, 0, 1, 1, 1, 0, 0, 0, -1, -1, -1]
-        );
-
-        let mut lookup = HashMap::new();
-        assert_eq!(pattern(1, 3, &mut lookup).to_owned(), vec![1, 0, -1]);
+        assert_eq!(pattern_for_pos(&BASE_PATTERN, 1, 3), vec![1, 0, -1]);
         assert_eq!(
-            pattern(2, 7, &mut lookup).to_owned(),
+            pattern_for_pos(&BASE_PATTERN, 2, 7),
             vec![0, 1, 1, 0, 0, -1, -1]
         );
         assert_eq!(
-            pattern(3, 11, &mut lookup).to_owned(),
+            pattern_for_pos(&BASE_PATTERN, 3, 11),
             vec![0, 0, 1, 1, 1, 0, 0, 0, -1, -1, -1]
         );
-        assert_eq!(lookup.len(), 3);
+    }
+
+    #[test]
+    fn aoc16_phase() {
+        use super::*;
+        let input: Vec<isize> = (0..97).map(|i| (i * 7 + 3) % 10).collect();
+        let bases: [&[isize]; 3] = [&BASE_PATTERN, &[1, -1], &[2, 0, -3, 1, 5]];
+
+        for base in bases.iter() {
+            let expected: Vec<isize> = (0..input.len())
+                .map(|i| {
+                    let p = pattern_for_pos(base, i + 1, input.len());
+                    let sum: isize = input.iter().zip(p.iter()).map(|(a, b)| a * b).sum();
+                    (sum % 10).abs()
+                })
+                .collect();
+            assert_eq!(phase(&input, base, true), expected);
+            assert_eq!(phase(&input, base, false), expected);
+        }
     }
 
     #[test]
     fn aoc16_run_1() {
         use super::*;
+        let fft = |input: &str, iterations| run_1(input, iterations, 8, &BASE_PATTERN, true);
 
-        assert_eq!(run_1("12345678", 1, 8), parse("48226158").unwrap().1);
-        assert_eq!(run_1("12345678", 2, 8), parse("34040438").unwrap().1);
-        assert_eq!(run_1("12345678", 3, 8), parse("03415518").unwrap().1);
-        assert_eq!(run_1("12345678", 4, 8), parse("01029498").unwrap().1);
+        assert_eq!(fft("12345678", 1), parse("48226158").unwrap().1);
+        assert_eq!(fft("12345678", 2), parse("34040438").unwrap().1);
+        assert_eq!(fft("12345678", 3), parse("03415518").unwrap().1);
+        assert_eq!(fft("12345678", 4), parse("01029498").unwrap().1);
 
         assert_eq!(
-            run_1("80871224585914546619083218645595", 100, 8),
+            fft("80871224585914546619083218645595", 100),
             parse("24176176").unwrap().1
         );
         assert_eq!(
-            run_1("19617804207202209144916044189917", 100, 8),
+            fft("19617804207202209144916044189917", 100),
             parse("73745418").unwrap().1
         );
         assert_eq!(
-            run_1("69317163492948606335995924319873", 100, 8),
+            fft("69317163492948606335995924319873", 100),
             parse("52432133").unwrap().1
         );
+
+        let input = fs::read_to_string("day16.txt").unwrap();
+        assert_eq!(fft(&input, 100), parse("42205986").unwrap().1);
+        assert_eq!(
+            run_1(&input, 100, 8, &BASE_PATTERN, false),
+            parse("42205986").unwrap().1
+        );
     }
 
     #[test]
@@ -191,15 +250,33 @@ mod tests {
     fn aoc16_run_2() {
         assert_eq!(
             super::run_2("03036732577212944063491565474664", 100, 8),
-            super::parse("84462026").unwrap().1
+            Ok(super::parse("84462026").unwrap().1)
         );
         assert_eq!(
             super::run_2("02935109699940807407585447034323", 100, 8),
-            super::parse("78725270").unwrap().1
+            Ok(super::parse("78725270").unwrap().1)
         );
         assert_eq!(
             super::run_2("03081770884921959731165446850517", 100, 8),
-            super::parse("53553731").unwrap().1
+            Ok(super::parse("53553731").unwrap().1)
+        );
+
+        let input = std::fs::read_to_string("day16.txt").unwrap();
+        assert_eq!(
+            super::run_2(&input, 100, 8),
+            Ok(super::parse("13270205").unwrap().1)
         );
     }
+
+    #[test]
+    fn aoc16_run_2_offset() {
+        use super::*;
+        assert!(run_2("12345678", 100, 8).is_err());
+
+        // The message is in the first half, so all digits are computed
+        let input = "0000005123";
+        let signal = input.repeat(REPEAT);
+        let full = run_1(&signal, 2, 13, &BASE_PATTERN, true);
+        assert_eq!(run_2(input, 2, 8), Ok(full[5..].to_vec()));
+    }
 }
@@ -1,3 +1,6 @@
+use crate::render::{
+    write_frames, Cell, FrameBuffer, Renderer, Terminal, WindowRenderer, FRAME_DELAY, MULT,
+};
 use std::fs;
 
 #[derive(Debug, PartialEq)]
@@ -23,8 +26,32 @@ impl MapItem {
             _ => Self::Empty,
         }
     }
+
+    fn to_char(&self) -> char {
+        match self {
+            Self::RobotUp => '^',
+            Self::RobotDown => 'v',
+            Self::RobotRight => '>',
+            Self::RobotLeft => '<',
+            Self::RobotDead => 'X',
+            Self::Scaffold => '#',
+            Self::Empty => '.',
+        }
+    }
+
+    fn cell(&self) -> Cell {
+        let color = match self {
+            Self::Scaffold => 0x808080,
+            Self::Empty => 0x404040,
+            Self::RobotDead => 0xff0000,
+            _ => 0x00ff00,
+        };
+        Cell::new(self.to_char(), color)
+    }
 }
 
+type Frame = Vec<Vec<MapItem>>;
+
 fn parse(input: &str) -> Vec<Vec<MapItem>> {
     let mut res = Vec::new();
     for r in input.lines().filter(|l| l.len() > 0) {
@@ -38,7 +65,28 @@ pub fn run() {
     let input = fs::read_to_string("day17.txt").unwrap();
 
     println!("day17-1: {}", run_1(&input));
-    //
+    println!("day17-2: {}", run_2(&input));
+
+    // cargo run 17 [terminal|window|dump]
+    match std::env::args().nth(2).as_deref() {
+        Some("terminal") => {
+            let frames = video(&input);
+            let (width, height) = frames_size(&frames);
+            show(&frames, &mut Terminal::new(width, height));
+        }
+        Some("window") => {
+            let frames = video(&input);
+            let (width, height) = frames_size(&frames);
+            show(
+                &frames,
+                &mut WindowRenderer::new("Aoc17-2", width, height, MULT),
+            );
+        }
+        Some("dump") => {
+            dump_frames(&video(&input), "day17-frames").unwrap();
+        }
+        _ => (),
+    }
 }
 
 fn count_intersections(map: &[Vec<MapItem>]) -> usize {
@@ -79,6 +127,134 @@ fn count_intersections(map: &[Vec<MapItem>]) -> usize {
     s
 }
 
+fn scaffold_at(map: &[Vec<MapItem>], x: isize, y: isize) -> bool {
+    if x < 0 || y < 0 {
+        return false;
+    }
+    map.get(y as usize)
+        .and_then(|r| r.get(x as usize))
+        .map(|i| *i == MapItem::Scaffold)
+        .unwrap_or(false)
+}
+
+fn find_robot(map: &[Vec<MapItem>]) -> ((isize, isize), (isize, isize)) {
+    for (y, row) in map.iter().enumerate() {
+        for (x, item) in row.iter().enumerate() {
+            let dir = match item {
+                MapItem::RobotUp => (0, -1),
+                MapItem::RobotDown => (0, 1),
+                MapItem::RobotLeft => (-1, 0),
+                MapItem::RobotRight => (1, 0),
+                _ => continue,
+            };
+            return ((x as isize, y as isize), dir);
+        }
+    }
+    unreachable!();
+}
+
+// Walk the scaffold from the robot, going straight as far as possible and only
+// turning at the ends, returns moves like "R,8". If the robot already faces
+// along the scaffold the first move is only the number of steps.
+fn find_path(map: &[Vec<MapItem>]) -> Vec<String> {
+    let ((mut x, mut y), (mut dx, mut dy)) = find_robot(map);
+    let mut path = Vec::new();
+
+    let mut steps = 0;
+    while scaffold_at(map, x + dx, y + dy) {
+        x += dx;
+        y += dy;
+        steps += 1;
+    }
+    if steps > 0 {
+        path.push(steps.to_string());
+    }
+
+    loop {
+        // Turning right in screen coordinates (y down)
+        let (turn, ndx, ndy) = if scaffold_at(map, x - dy, y + dx) {
+            ('R', -dy, dx)
+        } else if scaffold_at(map, x + dy, y - dx) {
+            ('L', dy, -dx)
+        } else {
+            return path;
+        };
+        dx = ndx;
+        dy = ndy;
+
+        let mut steps = 0;
+        while scaffold_at(map, x + dx, y + dy) {
+            x += dx;
+            y += dy;
+            steps += 1;
+        }
+        path.push(format!("{},{}", turn, steps));
+    }
+}
+
+const MAX_ROUTINE_LEN: usize = 20;
+
+fn fits(routine: &[String]) -> bool {
+    routine.join(",").len() <= MAX_ROUTINE_LEN
+}
+
+fn compress_rec(path: &[String], main: &mut Vec<usize>, functions: &mut Vec<Vec<String>>) -> bool {
+    if !main.is_empty() && main.len() * 2 - 1 > MAX_ROUTINE_LEN {
+        return false;
+    }
+    if path.is_empty() {
+        return true;
+    }
+
+    for f in 0..functions.len() {
+        if path.starts_with(&functions[f]) {
+            main.push(f);
+            if compress_rec(&path[functions[f].len()..], main, functions) {
+                return true;
+            }
+            main.pop();
+        }
+    }
+
+    if functions.len() < 3 {
+        for len in 1..=path.len() {
+            if !fits(&path[..len]) {
+                break;
+            }
+            functions.push(path[..len].to_vec());
+            main.push(functions.len() - 1);
+            if compress_rec(&path[len..], main, functions) {
+                return true;
+            }
+            main.pop();
+            functions.pop();
+        }
+    }
+
+    false
+}
+
+// Split the path into a main routine calling up to three movement functions,
+// returns the main routine and A, B and C, all as ASCII commands
+fn compress(path: &[String]) -> Option<(String, Vec<String>)> {
+    let mut main = Vec::new();
+    let mut functions = Vec::new();
+
+    if !compress_rec(path, &mut main, &mut functions) {
+        return None;
+    }
+
+    let main = main
+        .iter()
+        .map(|f| ((b'A' + *f as u8) as char).to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut functions: Vec<String> = functions.iter().map(|f| f.join(",")).collect();
+    // The robot always asks for all three functions, unused ones are empty
+    functions.resize(3, String::new());
+    Some((main, functions))
+}
+
 fn run_1(input: &str) -> usize {
     let mut cpu = crate::intcode::CPU::new(input);
 
@@ -88,6 +264,119 @@ fn run_1(input: &str) -> usize {
     count_intersections(&map)
 }
 
+fn run_robot(input: &str, video: bool) -> crate::intcode::CPU {
+    let mut cpu = crate::intcode::CPU::new(input);
+
+    cpu.run(&mut vec![]);
+    let camera = String::from_utf8(cpu.output.iter().map(|i| *i as u8).collect()).unwrap();
+    let map = parse(&camera);
+    let (main, functions) = compress(&find_path(&map)).unwrap();
+
+    let mut cpu = crate::intcode::CPU::new(input);
+    // Wake up the robot
+    cpu.memory[0] = 2;
+
+    let mut routines = vec![main];
+    routines.extend(functions);
+    routines.push(if video { "y" } else { "n" }.to_string());
+
+    let mut input: Vec<i64> = routines
+        .iter()
+        .flat_map(|r| r.bytes().chain(std::iter::once(b'\n')))
+        .map(|c| c as i64)
+        .collect();
+    cpu.run(&mut input);
+    cpu
+}
+
+fn run_2(input: &str) -> i64 {
+    // The collected dust is the last, non ASCII, value
+    *run_robot(input, false).output.last().unwrap()
+}
+
+fn is_map_line(line: &str) -> bool {
+    !line.is_empty() && line.chars().all(|c| ".#^v<>X".contains(c))
+}
+
+// The video feed is the camera image followed by the prompts and then one
+// frame per robot movement, frames are separated by an empty line
+fn parse_frames(stream: &str) -> Vec<Frame> {
+    let mut frames = Vec::new();
+    let mut frame: Frame = Vec::new();
+
+    for line in stream.lines() {
+        if is_map_line(line) {
+            frame.push(line.chars().map(MapItem::parse).collect());
+        } else if !frame.is_empty() {
+            frames.push(frame);
+            frame = Vec::new();
+        }
+    }
+    if !frame.is_empty() {
+        frames.push(frame);
+    }
+    frames
+}
+
+fn video(input: &str) -> Vec<Frame> {
+    let cpu = run_robot(input, true);
+    let stream: String = cpu
+        .output
+        .iter()
+        .filter(|c| **c < 128)
+        .map(|c| *c as u8 as char)
+        .collect();
+    parse_frames(&stream)
+}
+
+// Large enough for every frame
+fn frames_size(frames: &[Frame]) -> (usize, usize) {
+    let width = frames
+        .iter()
+        .flat_map(|f| f.iter().map(|r| r.len()))
+        .max()
+        .unwrap_or(0);
+    let height = frames.iter().map(|f| f.len()).max().unwrap_or(0);
+    (width, height)
+}
+
+// Frames can be smaller than the display, clears what they don't cover
+fn draw_frame(
+    frame: &[Vec<MapItem>],
+    (width, height): (usize, usize),
+    renderer: &mut dyn Renderer,
+) {
+    for y in 0..height {
+        for x in 0..width {
+            let item = frame.get(y).and_then(|r| r.get(x));
+            renderer.set(x, y, item.unwrap_or(&MapItem::Empty).cell());
+        }
+    }
+}
+
+// Draws the frames one after the other, stops early if the display is closed
+fn show(frames: &[Frame], renderer: &mut dyn Renderer) {
+    let size = frames_size(frames);
+    for frame in frames {
+        draw_frame(frame, size, renderer);
+        if !renderer.present() {
+            break;
+        }
+        std::thread::sleep(FRAME_DELAY);
+    }
+}
+
+// Writes every frame to its own PPM
+fn dump_frames(frames: &[Frame], dir: &str) -> std::io::Result<usize> {
+    let (width, height) = frames_size(frames);
+    let images = frames.iter().map(|frame| {
+        let mut fb = FrameBuffer::new(width, height);
+        draw_frame(frame, (width, height), &mut fb);
+        fb
+    });
+    write_frames(dir, images, MULT)
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -118,4 +407,97 @@ mod tests {
         let map = parse(map);
         assert_eq!(count_intersections(&map), 76);
     }
+
+    #[test]
+    fn aoc17_run_2() {
+        use super::*;
+        let map = "#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......";
+        let map = parse(map);
+        let path = find_path(&map);
+        assert_eq!(
+            path.join(","),
+            "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2"
+        );
+
+        let (main, functions) = compress(&path).unwrap();
+        assert!(main.len() <= MAX_ROUTINE_LEN);
+        assert!(functions.iter().all(|f| f.len() <= MAX_ROUTINE_LEN));
+
+        let expanded: Vec<&str> = main
+            .split(',')
+            .map(|f| functions[(f.as_bytes()[0] - b'A') as usize].as_str())
+            .collect();
+        assert_eq!(expanded.join(","), path.join(","));
+
+        // Starting out straight ahead, not with a turn
+        let map = parse(
+            "..#..
+..#..
+..^..",
+        );
+        assert_eq!(find_path(&map), vec!["2"]);
+        let map = parse(
+            "####.
+...#.
+...^.",
+        );
+        assert_eq!(find_path(&map), vec!["2", "L,3"]);
+
+        // Functions that aren't needed are sent as empty lines
+        let (main, functions) = compress(&vec!["R,4".to_string(); 3]).unwrap();
+        assert_eq!(main, "A,A,A");
+        assert_eq!(functions, vec!["R,4", "", ""]);
+
+        let input = fs::read_to_string("day17.txt").unwrap();
+        assert_eq!(run_1(&input), 2508);
+        assert_eq!(run_2(&input), 799463);
+    }
+
+    #[test]
+    fn aoc17_frames() {
+        use super::*;
+        let stream = "..#..
+..^..
+
+Main:
+Function A:
+Function B:
+Function C:
+Continuous video feed?
+
+..#..
+..>..
+
+..#..
+..X..
+";
+        let frames = parse_frames(stream);
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0][1][2], MapItem::RobotUp);
+        assert_eq!(frames[1][1][2], MapItem::RobotRight);
+        assert_eq!(frames[2][1][2], MapItem::RobotDead);
+
+        let mut fb = FrameBuffer::new(5, 2);
+        show(&frames, &mut fb);
+        assert_eq!(fb.presented, 3);
+        assert_eq!(fb.to_string(), "..#..\n..X..\n");
+        assert_eq!(fb.get(2, 1).color, 0xff0000);
+
+        let dir = std::env::temp_dir().join("aoc17_frames");
+        assert_eq!(dump_frames(&frames, dir.to_str().unwrap()).unwrap(), 3);
+    }
 }
@@ -1,5 +1,329 @@
-
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs;
 
 pub fn run() {
-    //
+    // The puzzle input isn't checked in, don't fail the whole run without it
+    let input = match fs::read_to_string("day18.txt") {
+        Ok(input) => input,
+        Err(e) => {
+            println!("18: can't read day18.txt, {}", e);
+            return;
+        }
+    };
+    println!("18:1 {}", run_1(&input));
+    println!("18:2 {}", run_2(&input));
+}
+
+type Pos = (usize, usize);
+type Grid = Vec<Vec<char>>;
+
+fn parse(input: &str) -> Grid {
+    input
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.trim().chars().collect())
+        .collect()
+}
+
+// Keys and doors are bits in a u32, 'a' and 'A' are bit 0
+fn bit(c: char) -> u32 {
+    1 << (c.to_ascii_lowercase() as u8 - b'a')
+}
+
+// Nodes 0-25 are the keys, the robot starts come after them
+const ROBOT: usize = 26;
+
+#[derive(Debug, PartialEq)]
+struct Edge {
+    key: usize,
+    dist: usize,
+    // The doors on the way to the key
+    doors: u32,
+}
+
+// Every key that can be reached from `from`, walking through doors
+fn reachable(grid: &Grid, from: Pos) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(from);
+    queue.push_back((from, 0, 0));
+
+    while let Some(((x, y), dist, doors)) = queue.pop_front() {
+        let c = grid[y][x];
+        let doors = match c {
+            'A'..='Z' => doors | bit(c),
+            _ => doors,
+        };
+        if c.is_ascii_lowercase() && (x, y) != from {
+            edges.push(Edge {
+                key: (c as u8 - b'a') as usize,
+                dist,
+                doors,
+            });
+        }
+
+        for (nx, ny) in &[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+            if grid[*ny][*nx] != '#' && visited.insert((*nx, *ny)) {
+                queue.push_back(((*nx, *ny), dist + 1, doors));
+            }
+        }
+    }
+
+    edges
+}
+
+struct Graph {
+    // Edges from every node, empty for keys that aren't in the vault
+    edges: Vec<Vec<Edge>>,
+    robots: usize,
+    keys: u32,
+}
+
+fn graph(grid: &Grid) -> Graph {
+    let mut edges: Vec<Vec<Edge>> = (0..ROBOT).map(|_| Vec::new()).collect();
+    let mut starts = Vec::new();
+    let mut keys = 0;
+
+    for (y, row) in grid.iter().enumerate() {
+        for (x, c) in row.iter().enumerate() {
+            match c {
+                'a'..='z' => {
+                    edges[(*c as u8 - b'a') as usize] = reachable(grid, (x, y));
+                    keys |= bit(*c);
+                }
+                '@' => starts.push(reachable(grid, (x, y))),
+                _ => (),
+            }
+        }
+    }
+
+    let robots = starts.len();
+    edges.extend(starts);
+    Graph {
+        edges,
+        robots,
+        keys,
+    }
+}
+
+// Dijkstra over the nodes the robots stand on and the keys collected so
+// far, moving one robot to a new key at a time
+fn shortest(grid: &Grid) -> usize {
+    let graph = graph(grid);
+    let start: Vec<usize> = (0..graph.robots).map(|r| ROBOT + r).collect();
+
+    let mut best = HashMap::new();
+    let mut heap = BinaryHeap::new();
+    best.insert((start.clone(), 0), 0);
+    heap.push(Reverse((0, start, 0)));
+
+    while let Some(Reverse((dist, robots, keys))) = heap.pop() {
+        if keys == graph.keys {
+            return dist;
+        }
+        if best[&(robots.clone(), keys)] < dist {
+            continue;
+        }
+
+        for (r, node) in robots.iter().enumerate() {
+            for e in graph.edges[*node].iter() {
+                if keys & (1 << e.key) != 0 || e.doors & !keys != 0 {
+                    continue;
+                }
+
+                let mut next = robots.clone();
+                next[r] = e.key;
+                let state = (next, keys | (1 << e.key));
+                let d = dist + e.dist;
+                if best.get(&state).is_none_or(|b| d < *b) {
+                    best.insert(state.clone(), d);
+                    heap.push(Reverse((d, state.0, state.1)));
+                }
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+// Replace the single entrance with four robots in separate quadrants
+fn split(grid: &mut Grid) {
+    let starts: Vec<Pos> = grid
+        .iter()
+        .enumerate()
+        .flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '@')
+                .map(move |(x, _)| (x, y))
+        })
+        .collect();
+    if starts.len() != 1 {
+        return;
+    }
+
+    let (x, y) = starts[0];
+    let center = ["@#@", "###", "@#@"];
+    for (dy, row) in center.iter().enumerate() {
+        for (dx, c) in row.chars().enumerate() {
+            grid[y + dy - 1][x + dx - 1] = c;
+        }
+    }
+}
+
+fn run_1(input: &str) -> usize {
+    shortest(&parse(input))
+}
+
+fn run_2(input: &str) -> usize {
+    let mut grid = parse(input);
+    split(&mut grid);
+    shortest(&grid)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn aoc18_reachable() {
+        use super::*;
+        let grid = parse(
+            "#########
+#b.A.@.a#
+#########",
+        );
+        let edges = reachable(&grid, (5, 1));
+        assert_eq!(
+            edges,
+            vec![
+                Edge {
+                    key: 0,
+                    dist: 2,
+                    doors: 0
+                },
+                Edge {
+                    key: 1,
+                    dist: 4,
+                    doors: bit('A')
+                },
+            ]
+        );
+        assert_eq!(graph(&grid).keys, 0b11);
+    }
+
+    #[test]
+    fn aoc18_run_1() {
+        use super::*;
+        let examples = [
+            (
+                "#########
+#b.A.@.a#
+#########",
+                8,
+            ),
+            (
+                "########################
+#f.D.E.e.C.b.A.@.a.B.c.#
+######################.#
+#d.....................#
+########################",
+                86,
+            ),
+            (
+                "########################
+#...............b.C.D.f#
+#.######################
+#.....@.a.B.c.d.A.e.F.g#
+########################",
+                132,
+            ),
+            (
+                "#################
+#i.G..c...e..H.p#
+########.########
+#j.A..b...f..D.o#
+########@########
+#k.E..a...g..B.n#
+########.########
+#l.F..d...h..C.m#
+#################",
+                136,
+            ),
+            (
+                "########################
+#@..............ac.GI.b#
+###d#e#f################
+###A#B#C################
+###g#h#i################
+########################",
+                81,
+            ),
+        ];
+        for (map, steps) in examples.iter() {
+            assert_eq!(run_1(map), *steps);
+        }
+    }
+
+    #[test]
+    fn aoc18_run_2() {
+        use super::*;
+        let mut grid = parse(
+            "#######
+#a.#Cd#
+##...##
+##.@.##
+##...##
+#cB#Ab#
+#######",
+        );
+        split(&mut grid);
+        assert_eq!(
+            grid[2..5],
+            parse(
+                "##@#@##
+#######
+##@#@##"
+            )[..]
+        );
+        assert_eq!(shortest(&grid), 8);
+
+        let examples = [
+            (
+                "###############
+#d.ABC.#.....a#
+######@#@######
+###############
+######@#@######
+#b.....#.....c#
+###############",
+                24,
+            ),
+            (
+                "#############
+#DcBa.#.GhKl#
+#.###@#@#I###
+#e#d#####j#k#
+###C#@#@###J#
+#fEbA.#.FgHi#
+#############",
+                32,
+            ),
+            (
+                "#############
+#g#f.D#..h#l#
+#F###e#E###.#
+#dCba@#@BcIJ#
+#############
+#nK.L@#@G...#
+#M###N#H###.#
+#o#m..#i#jk.#
+#############",
+                72,
+            ),
+        ];
+        for (map, steps) in examples.iter() {
+            assert_eq!(run_2(map), *steps);
+        }
+    }
 }
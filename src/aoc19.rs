@@ -1,10 +1,18 @@
-use rayon::prelude::*;
+use std::collections::HashMap;
 use std::fs;
 
 pub fn run() {
     let input = fs::read_to_string("day19.txt").unwrap();
     println!("19:1 - {}", run_1(&input));
     println!("19:2 - {}", run_2(&input, 100));
+
+    // cargo run 19 render
+    if std::env::args().nth(2).as_deref() == Some("render") {
+        let mut beam = intcode_beam(&input);
+        let square = beam.fits(5);
+        print!("{}", beam.render(50, 50, Some((square, 5))));
+        println!("width at row 49: {}", beam.width(49));
+    }
 }
 
 fn runner_1(mut cpu: crate::intcode::CPU, x: i64, y: i64) -> i64 {
@@ -15,62 +23,138 @@ fn runner_1(mut cpu: crate::intcode::CPU, x: i64, y: i64) -> i64 {
     cpu.output[0]
 }
 
-fn run_1(input: &str) -> i64 {
-    let xs: Vec<i64> = (0..50).collect();
-    let ys = xs.clone();
-
-    let cpu = crate::intcode::CPU::new(input);
-
-    xs.par_iter()
-        .map(|x: &i64| {
-            ys.par_iter()
-                .map(|y| {
-                    //
-                    runner_1(cpu.clone(), *x, *y)
-                })
-                .sum::<i64>()
-        })
-        .sum()
-}
-
 fn is_valid(cpu: crate::intcode::CPU, x: i64, y: i64) -> bool {
     runner_1(cpu, x, y) == 1
 }
 
-fn run_2(input: &str, width: i64) -> i64 {
-    let cpu = crate::intcode::CPU::new(input);
+// The beam is a cone from (0, 0), on every row the lit cells are next to each
+// other and both edges only move right further down. So a row is scanned
+// from the edges of the row above, and only a few cells per row are probed.
+struct Beam<F: Fn(i64, i64) -> bool> {
+    probe: F,
+    cache: HashMap<(i64, i64), bool>,
+    // First and last lit x on every row scanned so far, None for rows where
+    // the beam is too narrow to hit any cell
+    rows: Vec<Option<(i64, i64)>>,
+}
 
-    // start wide of the beam
-    let mut cur_x = 0;
+impl<F: Fn(i64, i64) -> bool> Beam<F> {
+    fn new(probe: F) -> Self {
+        Beam {
+            probe,
+            cache: HashMap::new(),
+            rows: Vec::new(),
+        }
+    }
 
-    // First find left edge on cur_y, we need to do this as there are some empty rows in the
-    // beginning
-    let mut cur_y = 10;
+    fn is_lit(&mut self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        let probe = &self.probe;
+        *self.cache.entry((x, y)).or_insert_with(|| probe(x, y))
+    }
+
+    // The edges of the row above, or where to start looking
+    fn prev_edges(&self) -> (i64, i64) {
+        self.rows.iter().rev().find_map(|r| *r).unwrap_or((0, 0))
+    }
 
-    loop {
-        cur_y += 1;
+    fn scan_row(&mut self) {
+        let y = self.rows.len() as i64;
+        let (prev_left, prev_right) = self.prev_edges();
 
-        // Follow the left edge
-        while !is_valid(cpu.clone(), cur_x, cur_y) {
-            cur_x += 1;
-        }
+        // Close to the origin rows can be empty, give up looking for the
+        // left edge well past where the beam could be
+        let limit = prev_right.max(prev_left) + 10 * (y + 1);
+        let left = (prev_left..=limit).find(|x| self.is_lit(*x, y));
+
+        let edges = left.map(|left| {
+            let mut right = prev_right.max(left);
+            if !self.is_lit(right, y) {
+                right = left;
+            }
+            while self.is_lit(right + 1, y) {
+                right += 1;
+            }
+            (left, right)
+        });
+        self.rows.push(edges);
+    }
 
-        // Make sure we're all the way to the left
-        while is_valid(cpu.clone(), cur_x - 1, cur_y) {
-            cur_x -= 1;
+    fn row(&mut self, y: i64) -> Option<(i64, i64)> {
+        while self.rows.len() as i64 <= y {
+            self.scan_row();
         }
+        self.rows[y as usize]
+    }
+
+    fn width(&mut self, y: i64) -> i64 {
+        self.row(y).map_or(0, |(left, right)| right - left + 1)
+    }
+
+    // Number of lit cells with x0 <= x < x1 and y0 <= y < y1
+    fn count(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64)) -> i64 {
+        (y0..y1)
+            .filter_map(|y| self.row(y))
+            .map(|(left, right)| (right.min(x1 - 1) - left.max(x0) + 1).max(0))
+            .sum()
+    }
 
-        // If the top_right corner is ok, we're good
-        let top_right = (cur_x + width - 1, cur_y - width + 1);
-        if !is_valid(cpu.clone(), top_right.0, top_right.1) {
-            continue;
+    // Top left corner of the square closest to the origin that fits in the
+    // beam. With the bottom left corner on the left edge of a row, it fits
+    // if the top row reaches far enough right.
+    fn fits(&mut self, size: i64) -> (i64, i64) {
+        let mut bottom = size - 1;
+        loop {
+            if let Some((left, _)) = self.row(bottom) {
+                let top = bottom - size + 1;
+                if let Some((_, right)) = self.row(top) {
+                    if right >= left + size - 1 {
+                        return (left, top);
+                    }
+                }
+            }
+            bottom += 1;
         }
+    }
 
-        // we're bottom left corner, get coord of top left corner
-        return cur_x * 10000 + (cur_y - width + 1);
+    // The scanned rows as '#' and '.', with a square drawn as 'O'
+    fn render(&mut self, width: i64, height: i64, square: Option<((i64, i64), i64)>) -> String {
+        let mut res = String::new();
+        for y in 0..height {
+            let row = self.row(y);
+            for x in 0..width {
+                let in_square = square.is_some_and(|((sx, sy), size)| {
+                    x >= sx && x < sx + size && y >= sy && y < sy + size
+                });
+                let lit = row.is_some_and(|(left, right)| x >= left && x <= right);
+                res.push(match (in_square, lit) {
+                    (true, _) => 'O',
+                    (false, true) => '#',
+                    (false, false) => '.',
+                });
+            }
+            res.push('\n');
+        }
+        res
     }
 }
 
+fn intcode_beam(input: &str) -> Beam<impl Fn(i64, i64) -> bool> {
+    let cpu = crate::intcode::CPU::new(input);
+    Beam::new(move |x, y| is_valid(cpu.clone(), x, y))
+}
+
+fn run_1(input: &str) -> i64 {
+    intcode_beam(input).count((0, 0), (50, 50))
+}
+
+fn run_2(input: &str, width: i64) -> i64 {
+    let (x, y) = intcode_beam(input).fits(width);
+    x * 10000 + y
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -94,10 +178,100 @@ mod tests {
         assert!(is_valid(cpu.clone(), 5, 3));
         assert!(is_valid(cpu.clone(), 7, 4));
     }
+
+    // Turns a drawn beam into a probe, '#' and 'O' are lit
+    fn grid_probe(grid: &'static str) -> impl Fn(i64, i64) -> bool {
+        let rows: Vec<&[u8]> = grid.lines().map(|l| l.as_bytes()).collect();
+        move |x, y| {
+            rows.get(y as usize)
+                .and_then(|r| r.get(x as usize))
+                .is_some_and(|c| *c != b'.')
+        }
+    }
+
+    #[test]
+    fn aoc19_run_1() {
+        use super::*;
+        let grid = "#.........
+.#........
+..##......
+...###....
+....###...
+.....####.
+......####
+......####
+.......###
+........##
+";
+        let mut beam = Beam::new(grid_probe(grid));
+        assert_eq!(beam.count((0, 0), (10, 10)), 27);
+        assert_eq!(beam.count((5, 5), (10, 10)), 17);
+        assert_eq!(beam.width(4), 3);
+        assert_eq!(beam.render(10, 10, None), grid);
+
+        // Same as probing every point
+        let input = fs::read_to_string("day19.txt").unwrap();
+        let cpu = crate::intcode::CPU::new(&input);
+        let mut beam = intcode_beam(&input);
+        let all = (0..50)
+            .flat_map(|y| (0..50).map(move |x| (x, y)))
+            .filter(|(x, y)| is_valid(cpu.clone(), *x, *y))
+            .count();
+        assert_eq!(beam.count((0, 0), (50, 50)), all as i64);
+        // Far fewer probes than the 2500 points
+        assert!(beam.cache.len() < 500);
+    }
+
     #[test]
     fn aoc19_run_2() {
         use super::*;
+        let grid = "#.......................................
+.#......................................
+..##....................................
+...###..................................
+....###.................................
+.....####...............................
+......#####.............................
+......######............................
+.......#######..........................
+........########........................
+.........#########......................
+..........#########.....................
+...........##########...................
+...........############.................
+............############................
+.............#############..............
+..............##############............
+...............###############..........
+................###############.........
+................#################.......
+.................########OOOOOOOOOO.....
+..................#######OOOOOOOOOO#....
+...................######OOOOOOOOOO###..
+....................#####OOOOOOOOOO#####
+.....................####OOOOOOOOOO#####
+.....................####OOOOOOOOOO#####
+......................###OOOOOOOOOO#####
+.......................##OOOOOOOOOO#####
+........................#OOOOOOOOOO#####
+.........................OOOOOOOOOO#####
+..........................##############
+..........................##############
+...........................#############
+............................############
+.............................###########
+";
+        let mut beam = Beam::new(grid_probe(grid));
+        assert_eq!(beam.fits(10), (25, 20));
+        assert_eq!(beam.render(40, 35, Some(((25, 20), 10))), grid);
+
         let input = fs::read_to_string("day19.txt").unwrap();
-        assert_eq!(run_2(&input, 10), 25 * 10000 + 20);
+        let cpu = crate::intcode::CPU::new(&input);
+        let (x, y) = intcode_beam(&input).fits(100);
+        assert!(is_valid(cpu.clone(), x + 99, y));
+        assert!(is_valid(cpu.clone(), x, y + 99));
+        assert!(!is_valid(cpu.clone(), x - 1, y + 99));
+        assert!(!is_valid(cpu.clone(), x + 99, y - 1));
+        assert_eq!(run_2(&input, 100), 13530764);
     }
 }
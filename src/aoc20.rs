@@ -1,3 +1,4 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 
 pub fn run() {
@@ -13,17 +14,42 @@ enum Type {
     Portal(String),
 }
 
+type Pos = (usize, usize);
+
+// The label of a portal next to (x, y), the letters are read left to right
+// or top to bottom whichever side of the passage they are on
+fn label(grid: &[Vec<char>], (x, y): Pos) -> Option<String> {
+    let at = |x: usize, y: usize| {
+        grid.get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .filter(|c| c.is_ascii_uppercase())
+    };
+
+    let pairs = [
+        (at(x, y.wrapping_sub(2)), at(x, y.wrapping_sub(1))),
+        (at(x, y + 1), at(x, y + 2)),
+        (at(x.wrapping_sub(2), y), at(x.wrapping_sub(1), y)),
+        (at(x + 1, y), at(x + 2, y)),
+    ];
+    pairs.iter().find_map(|p| match p {
+        (Some(a), Some(b)) => Some(format!("{}{}", a, b)),
+        _ => None,
+    })
+}
+
 fn parse(input: &str) -> Vec<Vec<Type>> {
+    let grid: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
+
     let mut map = Vec::new();
-    let lns = input.lines();
-    for (_row_no, row) in lns.enumerate() {
+    for (y, row) in grid.iter().enumerate() {
         let mut map_row = Vec::new();
-        for p in row.chars() {
+        for (x, p) in row.iter().enumerate() {
             match p {
-                '.' => {
-                    // Do we have portal names north of?
-                    map_row.push(Type::Passage);
-                }
+                '.' => match label(&grid, (x, y)) {
+                    Some(name) => map_row.push(Type::Portal(name)),
+                    None => map_row.push(Type::Passage),
+                },
                 _ => map_row.push(Type::Wall),
             }
         }
@@ -32,13 +58,102 @@ fn parse(input: &str) -> Vec<Vec<Type>> {
     map
 }
 
-fn run_1(input: &str) -> i64 {
-    let _map = parse(input);
-    0
+struct Maze {
+    map: Vec<Vec<Type>>,
+    // Where every portal leads and if it's on the outer edge
+    portals: HashMap<Pos, (Pos, bool)>,
+    start: Pos,
+    end: Pos,
 }
 
-fn run_2(_input: &str) -> i64 {
-    unreachable!();
+impl Maze {
+    fn new(input: &str) -> Self {
+        let map = parse(input);
+        let height = map.len();
+        let width = map.iter().map(|r| r.len()).max().unwrap_or(0);
+
+        let mut by_name: HashMap<&str, Vec<Pos>> = HashMap::new();
+        for (y, row) in map.iter().enumerate() {
+            for (x, t) in row.iter().enumerate() {
+                if let Type::Portal(name) = t {
+                    by_name.entry(name).or_default().push((x, y));
+                }
+            }
+        }
+
+        // The passages of the outer edge are two steps in from the labels
+        let outer = |(x, y): Pos| x == 2 || y == 2 || x + 3 == width || y + 3 == height;
+        let mut portals = HashMap::new();
+        for ends in by_name.values() {
+            if let [a, b] = ends[..] {
+                portals.insert(a, (b, outer(a)));
+                portals.insert(b, (a, outer(b)));
+            }
+        }
+
+        Maze {
+            start: by_name["AA"][0],
+            end: by_name["ZZ"][0],
+            map,
+            portals,
+        }
+    }
+
+    fn open(&self, (x, y): Pos) -> bool {
+        !matches!(
+            self.map.get(y).and_then(|row| row.get(x)),
+            Some(Type::Wall) | None
+        )
+    }
+
+    // Breadth first from AA to ZZ. When `recursive` the inner portals go one
+    // level down and the outer ones one level up, ZZ is only open on level 0.
+    fn shortest(&self, recursive: bool) -> Option<usize> {
+        // Deeper than one level per portal never gets back up
+        let max_level = self.portals.len();
+
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert((self.start, 0));
+        queue.push_back((self.start, 0, 0));
+
+        while let Some((pos, level, dist)) = queue.pop_front() {
+            if pos == self.end && level == 0 {
+                return Some(dist);
+            }
+
+            let (x, y) = pos;
+            let mut next: Vec<(Pos, usize)> = vec![(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                .into_iter()
+                .filter(|p| self.open(*p))
+                .map(|p| (p, level))
+                .collect();
+            if let Some((to, outer)) = self.portals.get(&pos) {
+                match (recursive, outer) {
+                    (false, _) => next.push((*to, level)),
+                    (true, true) if level > 0 => next.push((*to, level - 1)),
+                    (true, false) if level < max_level => next.push((*to, level + 1)),
+                    _ => (),
+                }
+            }
+
+            for state in next {
+                if visited.insert(state) {
+                    queue.push_back((state.0, state.1, dist + 1));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+fn run_1(input: &str) -> usize {
+    Maze::new(input).shortest(false).unwrap()
+}
+
+fn run_2(input: &str) -> usize {
+    Maze::new(input).shortest(true).unwrap()
 }
 
 #[cfg(test)]
@@ -68,5 +183,23 @@ FG..#########.....#
              Z       "#;
         let map = parse(input);
         assert_eq!(map[2][9], Type::Portal("AA".to_owned()));
+        assert_eq!(map[8][2], Type::Portal("BC".to_owned()));
+        assert_eq!(map[3][10], Type::Passage);
+        assert_eq!(map[7][9], Type::Wall);
+
+        let maze = Maze::new(input);
+        assert_eq!(maze.start, (9, 2));
+        assert_eq!(maze.end, (13, 16));
+        // BC, DE and FG in both directions
+        assert_eq!(maze.portals.len(), 6);
+        assert_eq!(maze.portals[&(2, 8)], ((9, 6), true));
+        assert_eq!(maze.portals[&(9, 6)], ((2, 8), false));
+
+        assert_eq!(run_1(input), 23);
+        assert_eq!(run_2(input), 26);
+
+        let input = fs::read_to_string("day20.txt").unwrap();
+        assert_eq!(run_1(&input), 664);
+        assert_eq!(run_2(&input), 7334);
     }
 }
@@ -2,7 +2,12 @@ use std::fs;
 
 pub fn run() {
     let input = fs::read_to_string("day21.txt").unwrap();
-    println!("21:1 {:?}", run_1(&input));
+    for (part, res) in [(1, run_1(&input)), (2, run_2(&input))].iter() {
+        match res {
+            Ok(damage) => println!("21:{} {}", part, damage),
+            Err(e) => println!("21:{} failed, {}", part, e),
+        }
+    }
 }
 
 // ABCD
@@ -26,35 +31,272 @@ pub fn run() {
 // https://www.dcode.fr/boolean-expressions-calculator
 // (!a&&!b&&!c&&!d) || (a && !b && !c &&!d) || (!a&&b&&!c&&!d)
 
-fn run_1(program: &str) -> u128 {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Walk,
+    Run,
+}
+
+impl Mode {
+    fn sensors(self) -> usize {
+        match self {
+            Self::Walk => 4,
+            Self::Run => 9,
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            Self::Walk => "WALK",
+            Self::Run => "RUN",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Survived(i64),
+    // The hull the droid fell into, '#' is ground and '.' is a hole
+    Fell(String),
+}
+
+// Errors with the droid's output when it neither survived nor fell, like
+// when it doesn't understand the script
+fn run_script(program: &str, script: &[String], mode: Mode) -> Result<Outcome, String> {
     let mut cpu = crate::intcode::CPU::new(program);
 
+    // Skip the input prompt
     cpu.run(&mut vec![]);
-    let output =
-        String::from_utf8(cpu.output.iter().map(|v| *v as u8).collect::<Vec<u8>>()).unwrap();
     cpu.output.clear();
-    println!("{}", output);
 
-    let input = [
-        "NOT A J", "NOT B T", "AND D T", "OR T J", "NOT C T", "OR T J", "AND D J", "WALK", "",
-    ];
-    let input = input.join("\n");
-    let mut input: Vec<i64> = input.bytes().map(|v| v as i64).collect();
+    let mut input: Vec<i64> = script
+        .iter()
+        .map(|l| l.as_str())
+        .chain(std::iter::once(mode.command()))
+        .flat_map(|l| l.bytes().chain(std::iter::once(b'\n')))
+        .map(|v| v as i64)
+        .collect();
     cpu.run(&mut input);
 
-    // let output =
-    //     String::from_utf8(cpu.output.iter().map(|v| *v as u8).collect::<Vec<u8>>()).unwrap();
-    for i in cpu.output.iter() {
-        print!("{:x}", *i as u8);
+    // The hull damage is reported as a single value outside of the ASCII range
+    match cpu.output.last() {
+        Some(&damage) if damage > 127 => Ok(Outcome::Survived(damage)),
+        _ => {
+            let output: String = cpu.output.iter().map(|v| *v as u8 as char).collect();
+            parse_hull(&output).map(Outcome::Fell).ok_or(output)
+        }
     }
-    println!("{}", cpu.output.len());
-    cpu.output.clear();
+}
+
+// The death animation draws the droid above the hull, the first row with
+// ground in it is the hull the droid started on
+fn parse_hull(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find(|l| l.contains('#') && l.chars().all(|c| c == '#' || c == '.'))
+        .map(|l| l.to_string())
+}
+
+// Jump if any of `gaps` is a hole, D is ground to land on and, if given,
+// any of `follow` is ground so we can keep going after landing
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    gaps: Vec<char>,
+    follow: Vec<char>,
+}
 
-    "a57616c6b696e672e2e2eaa9a14".parse::<u128>().unwrap()
+fn sensor(c: char) -> usize {
+    (c as u8 - b'A') as usize
+}
+
+impl Candidate {
+    fn script(&self) -> Vec<String> {
+        let mut script = vec![format!("NOT {} J", self.gaps[0])];
+        for g in &self.gaps[1..] {
+            script.push(format!("NOT {} T", g));
+            script.push("OR T J".to_string());
+        }
+        script.push("AND D J".to_string());
+
+        if !self.follow.is_empty() {
+            // T can hold anything here, load it with the first sensor
+            script.push(format!("NOT {} T", self.follow[0]));
+            script.push("NOT T T".to_string());
+            for f in &self.follow[1..] {
+                script.push(format!("OR {} T", f));
+            }
+            script.push("AND T J".to_string());
+        }
+        script
+    }
+
+    fn jumps(&self, ground: &[bool]) -> bool {
+        self.gaps.iter().any(|g| !ground[sensor(*g)])
+            && ground[sensor('D')]
+            && (self.follow.is_empty() || self.follow.iter().any(|f| ground[sensor(*f)]))
+    }
+
+    // Simulate the droid on a known hull without running the Intcode program
+    fn survives(&self, hull: &str, mode: Mode) -> bool {
+        let hull: Vec<bool> = hull.chars().map(|c| c == '#').collect();
+        let is_ground = |p: usize| p >= hull.len() || hull[p];
+
+        let mut pos = 0;
+        while pos < hull.len() {
+            let ground: Vec<bool> = (1..=mode.sensors()).map(|d| is_ground(pos + d)).collect();
+            pos += if self.jumps(&ground) { 4 } else { 1 };
+            if !is_ground(pos) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn candidates(mode: Mode) -> Vec<Candidate> {
+    let gaps = ['A', 'B', 'C'];
+    let follow: Vec<char> = ('E'..='I').take(mode.sensors() - 4).collect();
+
+    let mut follows = vec![vec![]];
+    for (i, a) in follow.iter().enumerate() {
+        follows.push(vec![*a]);
+        for b in &follow[i + 1..] {
+            follows.push(vec![*a, *b]);
+        }
+    }
+
+    let mut res = Vec::new();
+    for f in &follows {
+        for mask in 1..(1 << gaps.len()) {
+            res.push(Candidate {
+                gaps: (0..gaps.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .map(|i| gaps[i])
+                    .collect(),
+                follow: f.clone(),
+            });
+        }
+    }
+    res
+}
+
+// Try candidates in turn, every time the droid falls the hull it fell on is
+// remembered and later candidates that can't cross it are skipped
+fn search(program: &str, mode: Mode) -> Result<(Candidate, i64), String> {
+    let mut hulls: Vec<String> = Vec::new();
+
+    for c in candidates(mode) {
+        if hulls.iter().any(|h| !c.survives(h, mode)) {
+            continue;
+        }
+
+        match run_script(program, &c.script(), mode)? {
+            Outcome::Survived(damage) => return Ok((c, damage)),
+            Outcome::Fell(hull) => hulls.push(hull),
+        }
+    }
+
+    Err(format!("no candidate survives in {:?} mode", mode))
+}
+
+fn run_1(program: &str) -> Result<i64, String> {
+    let script = [
+        "NOT A J", "NOT B T", "AND D T", "OR T J", "NOT C T", "OR T J", "AND D J",
+    ];
+    let script: Vec<String> = script.iter().map(|l| l.to_string()).collect();
+
+    match run_script(program, &script, Mode::Walk)? {
+        Outcome::Survived(damage) => Ok(damage),
+        Outcome::Fell(hull) => Err(format!("fell on {}", hull)),
+    }
+}
+
+fn run_2(program: &str) -> Result<i64, String> {
+    search(program, Mode::Run).map(|(_, damage)| damage)
 }
 
 #[cfg(test)]
 mod tests {
     #[test]
-    fn aoc21_parse() {}
+    fn aoc21_parse() {
+        use super::*;
+        let output = r#"Input instructions:
+
+Walking...
+
+
+Didn't make it across:
+
+.................
+.................
+@................
+#####.#..########
+
+.................
+.................
+.@...............
+#####.#..########
+"#;
+        assert_eq!(parse_hull(output), Some("#####.#..########".to_string()));
+        assert_eq!(parse_hull("Input instructions:\n\nInvalid operation; expected something like AND, OR, or NOT\n"), None);
+    }
+
+    #[test]
+    fn aoc21_script() {
+        use super::*;
+        let c = Candidate {
+            gaps: vec!['A', 'C'],
+            follow: vec!['E', 'H'],
+        };
+        assert_eq!(
+            c.script(),
+            vec![
+                "NOT A J", "NOT C T", "OR T J", "AND D J", "NOT E T", "NOT T T", "OR H T",
+                "AND T J"
+            ]
+        );
+    }
+
+    #[test]
+    fn aoc21_survives() {
+        use super::*;
+        let c = Candidate {
+            gaps: vec!['A'],
+            follow: vec![],
+        };
+        assert!(c.survives("#####...#########", Mode::Walk));
+        assert!(!c.survives("#####.#..########", Mode::Walk));
+
+        let c = Candidate {
+            gaps: vec!['A', 'B', 'C'],
+            follow: vec!['E', 'H'],
+        };
+        assert!(c.survives("#####.#..########", Mode::Run));
+        assert!(c.survives("#####.#.##..#####", Mode::Run));
+    }
+
+    #[test]
+    fn aoc21_candidates() {
+        use super::*;
+        assert_eq!(candidates(Mode::Walk).len(), 7);
+        assert_eq!(candidates(Mode::Run).len(), 7 * 16);
+        // 15 instructions at most, one of them is RUN
+        assert!(candidates(Mode::Run).iter().all(|c| c.script().len() < 15));
+    }
+
+    #[test]
+    fn aoc21_run() {
+        use super::*;
+        let input = fs::read_to_string("day21.txt").unwrap();
+        assert_eq!(run_1(&input), Ok(19350938));
+        assert_eq!(run_2(&input), Ok(1142986901));
+
+        let script = vec!["JUMP A J".to_string()];
+        assert!(run_script(&input, &script, Mode::Walk).is_err());
+        let script = vec!["NOT A J".to_string()];
+        assert!(matches!(
+            run_script(&input, &script, Mode::Walk),
+            Ok(Outcome::Fell(_))
+        ));
+    }
 }
@@ -1,10 +1,16 @@
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::newline, multi::separated_list1,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{multispace0, newline},
+    combinator::all_consuming,
+    multi::separated_list1,
+    sequence::terminated,
     IResult,
 };
+use std::fmt;
 use std::fs;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 enum Tech {
     Deal,
     DealWithIncrement(usize),
@@ -29,13 +35,165 @@ impl Tech {
 
     fn cut(i: &str) -> IResult<&str, Self> {
         let (i, _) = tag("cut ")(i)?;
-        let (i, cut) = crate::helper::i32_val(i)?;
+        let (i, cut) = crate::helper::i64_val(i)?;
         Ok((i, Self::Cut(cut as isize)))
     }
+
+    // Where the technique moves the card at position x in a deck of n cards
+    fn affine(&self, n: u64) -> Affine {
+        match self {
+            Self::Deal => Affine::new(n - 1, n - 1, n),
+            Self::DealWithIncrement(inc) => Affine::new(*inc as u64 % n, 0, n),
+            Self::Cut(pos) => Affine::new(1, (n as i64 - *pos as i64 % n as i64) as u64 % n, n),
+        }
+    }
+
+    // The technique that puts the cards back
+    fn inverse(&self, n: u64) -> Tech {
+        match self {
+            Self::Deal => Self::Deal,
+            Self::DealWithIncrement(inc) => {
+                Self::DealWithIncrement(inv_mod(*inc as u64 % n, n) as usize)
+            }
+            Self::Cut(pos) => Self::Cut(-pos),
+        }
+    }
+}
+
+// Products of two numbers below n don't fit in a u64 for the big deck
+fn mul_mod(a: u64, b: u64, n: u64) -> u64 {
+    (a as u128 * b as u128 % n as u128) as u64
+}
+
+// The x where a * x = 1 mod n, by the extended Euclidean algorithm. Panics
+// if a and n have a common factor.
+fn inv_mod(a: u64, n: u64) -> u64 {
+    let (mut t, mut new_t) = (0i128, 1i128);
+    let (mut r, mut new_r) = (n as i128, a as i128);
+    while new_r != 0 {
+        let q = r / new_r;
+        let next_t = t - q * new_t;
+        t = new_t;
+        new_t = next_t;
+        let next_r = r - q * new_r;
+        r = new_r;
+        new_r = next_r;
+    }
+    assert_eq!(r, 1, "{} has no inverse mod {}", a, n);
+    t.rem_euclid(n as i128) as u64
+}
+
+// Moves the card at position x to a * x + b mod n
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Affine {
+    a: u64,
+    b: u64,
+    n: u64,
+}
+
+impl Affine {
+    fn new(a: u64, b: u64, n: u64) -> Self {
+        Affine { a, b, n }
+    }
+
+    fn apply(&self, x: u64) -> u64 {
+        (mul_mod(self.a, x, self.n) + self.b) % self.n
+    }
+
+    // First self and then other
+    fn then(&self, other: &Affine) -> Affine {
+        Affine::new(
+            mul_mod(other.a, self.a, self.n),
+            (mul_mod(other.a, self.b, self.n) + other.b) % self.n,
+            self.n,
+        )
+    }
+
+    // Self repeated `times` times, by squaring
+    fn pow(&self, mut times: u64) -> Affine {
+        let mut res = Affine::new(1, 0, self.n);
+        let mut sq = *self;
+        while times > 0 {
+            if times & 1 == 1 {
+                res = res.then(&sq);
+            }
+            sq = sq.then(&sq);
+            times >>= 1;
+        }
+        res
+    }
+
+    // Moves the cards back, x = (y - b) / a
+    fn inverse(&self) -> Affine {
+        let inv_a = inv_mod(self.a, self.n);
+        Affine::new(inv_a, mul_mod(inv_a, self.n - self.b, self.n), self.n)
+    }
+}
+
+// In the same format as the input
+impl fmt::Display for Tech {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Deal => write!(f, "deal into new stack"),
+            Self::DealWithIncrement(inc) => write!(f, "deal with increment {}", inc),
+            Self::Cut(pos) => write!(f, "cut {}", pos),
+        }
+    }
+}
+
+// Techniques done one after the other, for any deck size
+#[derive(Debug, Clone, PartialEq)]
+struct Shuffle(Vec<Tech>);
+
+impl fmt::Display for Shuffle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for t in self.0.iter() {
+            writeln!(f, "{}", t)?;
+        }
+        Ok(())
+    }
+}
+
+impl Shuffle {
+    fn affine(&self, n: u64) -> Affine {
+        self.0
+            .iter()
+            .fold(Affine::new(1, 0, n), |f, t| f.then(&t.affine(n)))
+    }
+
+    // First self and then other
+    fn then(&self, other: &Shuffle) -> Shuffle {
+        Shuffle(self.0.iter().chain(other.0.iter()).cloned().collect())
+    }
+
+    fn inverse(&self, n: u64) -> Shuffle {
+        Shuffle(self.0.iter().rev().map(|t| t.inverse(n)).collect())
+    }
+
+    // The same shuffle as a deal with increment followed by a cut
+    fn simplify(&self, n: u64) -> Shuffle {
+        let f = self.affine(n);
+        Shuffle(vec![
+            Tech::DealWithIncrement(f.a as usize),
+            Tech::Cut(((n - f.b) % n) as isize),
+        ])
+    }
+
+    fn position_of(&self, card: u64, n: u64) -> u64 {
+        self.affine(n).apply(card)
+    }
+
+    fn card_at(&self, pos: u64, n: u64) -> u64 {
+        self.affine(n).inverse().apply(pos)
+    }
 }
 
+// Fails on anything but whitespace after the last technique
 fn parse_techs(i: &str) -> IResult<&str, Vec<Tech>> {
-    separated_list1(newline, Tech::parse)(i)
+    all_consuming(terminated(
+        separated_list1(newline, Tech::parse),
+        multispace0,
+    ))(i)
 }
 
 type Deck = Vec<usize>;
@@ -44,6 +202,23 @@ pub fn run() {
     let input = fs::read_to_string("day22.txt").unwrap();
     println!("22:1 {}", run_1(&input));
     println!("22:2 {}", run_2(&input));
+
+    // cargo run 22 simplify|card <pos>|position <card> [deck size]
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let (_, techs) = parse_techs(&input).unwrap();
+    let shuffle = Shuffle(techs);
+    let arg = |i: usize, default: u64| args.get(i).map_or(default, |a| a.parse().unwrap());
+    match args.first().map(|a| a.as_str()) {
+        Some("simplify") => {
+            let n = arg(1, 10007);
+            println!("Shuffle:\n{}", shuffle.simplify(n));
+            println!("Twice:\n{}", shuffle.then(&shuffle).simplify(n));
+            println!("Inverse:\n{}", shuffle.inverse(n).simplify(n));
+        }
+        Some("card") => println!("{}", shuffle.card_at(arg(1, 0), arg(2, 10007))),
+        Some("position") => println!("{}", shuffle.position_of(arg(1, 0), arg(2, 10007))),
+        _ => (),
+    }
 }
 
 fn run_1(program: &str) -> usize {
@@ -59,17 +234,15 @@ fn run_1(program: &str) -> usize {
     idx
 }
 
-fn run_2(program: &str) -> usize {
-    let deck = create_deck(119315717514047);
+// The card that ends up at position 2020 after shuffling the huge deck a
+// huge number of times
+fn run_2(program: &str) -> u64 {
     let (_, techs) = parse_techs(program).unwrap();
-    let deck = run_with_deck(deck, &techs);
-
-    let (idx, _) = deck
-        .iter()
-        .enumerate()
-        .find(|(_, v)| **v == 2020usize)
-        .unwrap();
-    idx
+    Shuffle(techs)
+        .affine(119315717514047)
+        .pow(101741582076661)
+        .inverse()
+        .apply(2020)
 }
 
 fn create_deck(num_cards: usize) -> Deck {
@@ -204,5 +377,107 @@ cut -1",
         let deck = create_deck(10);
         let deck = run_with_deck(deck, &techs);
         assert_eq!(deck, &[9, 2, 5, 8, 1, 4, 7, 0, 3, 6]);
+
+        // Same positions from the affine map
+        let f = Shuffle(techs).affine(10);
+        for (pos, card) in deck.iter().enumerate() {
+            assert_eq!(f.apply(*card as u64), pos as u64);
+        }
+
+        let input = fs::read_to_string("day22.txt").unwrap();
+        let (_, techs) = parse_techs(&input).unwrap();
+        assert_eq!(
+            Shuffle(techs).position_of(2019, 10007),
+            run_1(&input) as u64
+        );
+    }
+
+    #[test]
+    fn aoc22_run_2() {
+        assert_eq!(mul_mod(u64::MAX - 1, u64::MAX - 1, u64::MAX), 1);
+        assert_eq!(inv_mod(3, 10), 7);
+
+        let input = fs::read_to_string("day22.txt").unwrap();
+        let (_, techs) = parse_techs(&input).unwrap();
+
+        // Shuffling three times by hand and undoing it
+        let f = Shuffle(techs.clone()).affine(10007);
+        let mut deck = create_deck(10007);
+        for _ in 0..3 {
+            deck = run_with_deck(deck, &techs);
+        }
+        let back = f.pow(3).inverse();
+        for (pos, card) in deck.iter().enumerate().step_by(97) {
+            assert_eq!(back.apply(pos as u64), *card as u64);
+        }
+
+        assert_eq!(run_2(&input), 13224103523662);
+    }
+
+    // A shuffle of `len` techniques from a linear congruential generator
+    fn random_shuffle(mut seed: u64, len: usize, n: u64) -> Shuffle {
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            seed >> 33
+        };
+        Shuffle(
+            (0..len)
+                .map(|_| match next() % 3 {
+                    0 => Tech::Deal,
+                    1 => Tech::DealWithIncrement((1 + next() % (n - 1)) as usize),
+                    _ => Tech::Cut((next() % (2 * n - 1)) as isize - (n - 1) as isize),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn aoc22_shuffle() {
+        // Deck sizes need to be prime for every increment to work
+        for n in [53, 10007].iter() {
+            let n = *n;
+            let identity = create_deck(n as usize);
+            for seed in 0..5 {
+                let a = random_shuffle(seed, 20, n);
+                let b = random_shuffle(seed + 100, 7, n);
+                let deck = run_with_deck(identity.clone(), &a.0);
+
+                for (pos, card) in deck.iter().enumerate() {
+                    assert_eq!(a.card_at(pos as u64, n), *card as u64);
+                    assert_eq!(a.position_of(*card as u64, n), pos as u64);
+                }
+
+                let simple = a.simplify(n);
+                assert_eq!(simple.0.len(), 2);
+                // Round trip through the text format
+                assert_eq!(parse_techs(&simple.to_string()).unwrap().1, simple.0);
+                assert_eq!(run_with_deck(identity.clone(), &simple.0), deck);
+
+                assert_eq!(run_with_deck(deck.clone(), &a.inverse(n).0), identity);
+
+                assert_eq!(
+                    run_with_deck(identity.clone(), &a.then(&b).0),
+                    run_with_deck(deck.clone(), &b.0)
+                );
+                assert_eq!(a.then(&b).affine(n), a.affine(n).then(&b.affine(n)));
+            }
+        }
+
+        // Too big to shuffle by hand, the cuts don't fit in an i32
+        let n = 119315717514047;
+        for seed in 0..5 {
+            let a = random_shuffle(seed, 20, n);
+            let simple = a.simplify(n);
+            assert_eq!(parse_techs(&simple.to_string()).unwrap().1, simple.0);
+            assert_eq!(simple.affine(n), a.affine(n));
+            assert_eq!(a.inverse(n).affine(n), a.affine(n).inverse());
+        }
+
+        assert_eq!(
+            parse_techs("cut -1841799366945\n").unwrap().1,
+            vec![Tech::Cut(-1841799366945)]
+        );
+        assert!(parse_techs("cut 3\ndeal with increment x").is_err());
+        assert!(parse_techs("cut 3\nshuffle").is_err());
     }
 }
@@ -1,97 +1,278 @@
 use crate::intcode::CPU;
-use std::collections::{HashMap, HashSet};
+use std::collections::VecDeque;
 use std::fs;
 
 pub fn run() {
     let input = fs::read_to_string("day23.txt").unwrap();
     println!("23:1 {}", run_1(&input));
     println!("23:2 {}", run_2(&input));
+
+    // cargo run 23 log|json
+    let mode = std::env::args().nth(2);
+    if let Some(mode) = mode.as_deref() {
+        let mut network = Network::new(&input, NetworkOptions::default());
+        network.run(&mut WakeUp::default());
+        match mode {
+            "log" => fs::write("day23-log.txt", network.log_text()).unwrap(),
+            "json" => fs::write("day23-log.json", network.log_json()).unwrap(),
+            _ => (),
+        }
+    }
 }
 
-fn run_1(program: &str) -> i64 {
-    let mut cpus: Vec<CPU> = (0..50)
-        .into_iter()
-        .map(|i| {
-            let mut cpu = CPU::new(program);
-            let mut input = vec![i];
-            cpu.run(&mut input);
-            cpu
-        })
-        .collect();
-
-    let mut queues: HashMap<usize, Vec<i64>> = HashMap::new();
-
-    loop {
-        for (i, cpu) in cpus.iter_mut().enumerate() {
-            // Gather output and store in queue
-            for (oi, addr) in cpu.output.iter().enumerate().step_by(3) {
-                let queue = queues.entry(*addr as usize).or_insert(Vec::new());
-                queue.push(cpu.output[oi + 1]);
-                queue.push(cpu.output[oi + 2]);
-                if *addr == 255 {
-                    return cpu.output[oi + 2];
-                }
-            }
-            cpu.output.clear();
+const NAT: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Packet {
+    round: usize,
+    from: usize,
+    to: usize,
+    x: i64,
+    y: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum NatAction {
+    Wait,
+    // Send a packet to address 0
+    Send(i64, i64),
+    // Stop the network with the answer
+    Stop(i64),
+}
+
+trait NatPolicy {
+    // Called for every packet sent to the NAT
+    fn receive(&mut self, x: i64, y: i64) -> NatAction;
+
+    // Called when the network has been idle
+    fn idle(&mut self) -> NatAction;
+}
+
+// Part 1, stops at the first packet
+struct FirstPacket;
+
+impl NatPolicy for FirstPacket {
+    fn receive(&mut self, _x: i64, y: i64) -> NatAction {
+        NatAction::Stop(y)
+    }
 
-            let queue = queues.entry(i).or_insert(Vec::new());
-            if queue.len() > 0 {
-                cpu.run(queue);
-            } else {
-                cpu.run(&mut vec![-1]);
+    fn idle(&mut self) -> NatAction {
+        NatAction::Wait
+    }
+}
+
+// Part 2, wakes up address 0 with the last packet when the network is idle
+// and stops when it sends the same y twice in a row
+#[derive(Default)]
+struct WakeUp {
+    last: Option<(i64, i64)>,
+    sent_y: Option<i64>,
+}
+
+impl NatPolicy for WakeUp {
+    fn receive(&mut self, x: i64, y: i64) -> NatAction {
+        self.last = Some((x, y));
+        NatAction::Wait
+    }
+
+    fn idle(&mut self) -> NatAction {
+        match self.last {
+            Some((_, y)) if self.sent_y == Some(y) => NatAction::Stop(y),
+            Some((x, y)) => {
+                self.sent_y = Some(y);
+                NatAction::Send(x, y)
             }
+            None => NatAction::Wait,
         }
     }
 }
 
-fn run_2(program: &str) -> i64 {
-    let mut cpus: Vec<CPU> = (0..50)
-        .into_iter()
-        .map(|i| {
-            let mut cpu = CPU::new(program);
-            let mut input = vec![i];
-            cpu.run(&mut input);
-            cpu
-        })
-        .collect();
-
-    let mut queues: HashMap<usize, Vec<i64>> = HashMap::new();
-    let mut nat_vals = HashSet::new();
-    loop {
-        let mut all_idle = true;
-        for (i, cpu) in cpus.iter_mut().enumerate() {
-            // Gather output and store in queue
-            for (oi, addr) in cpu.output.iter().enumerate().step_by(3) {
-                let queue = queues.entry(*addr as usize).or_insert(Vec::new());
-                // Only store last values in the NAT
-                if *addr == 255 {
-                    queue.clear();
+struct NetworkOptions {
+    nodes: usize,
+    // The network is idle after this many rounds in a row where no packets
+    // were sent or received
+    idle_rounds: usize,
+}
+
+impl Default for NetworkOptions {
+    fn default() -> Self {
+        NetworkOptions {
+            nodes: 50,
+            idle_rounds: 2,
+        }
+    }
+}
+
+// Runs the nodes in address order, one round at a time. A node gets all
+// packets queued for it, or -1 if there are none, and runs until it needs
+// more input. Its packets are queued right away, so a node later in the
+// same round can receive them.
+struct Network {
+    nodes: Vec<CPU>,
+    queues: Vec<VecDeque<i64>>,
+    options: NetworkOptions,
+    round: usize,
+    // Every packet sent, also the ones to the NAT and to unknown addresses
+    log: Vec<Packet>,
+}
+
+impl Network {
+    fn new(program: &str, options: NetworkOptions) -> Self {
+        let nodes = (0..options.nodes)
+            .map(|i| {
+                let mut cpu = CPU::new(program);
+                cpu.run(&mut vec![i as i64]);
+                cpu
+            })
+            .collect();
+
+        Network {
+            nodes,
+            queues: vec![VecDeque::new(); options.nodes],
+            options,
+            round: 0,
+            log: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, x: i64, y: i64) {
+        self.log.push(Packet {
+            round: self.round,
+            from,
+            to,
+            x,
+            y,
+        });
+        if let Some(queue) = self.queues.get_mut(to) {
+            queue.push_back(x);
+            queue.push_back(y);
+        }
+    }
+
+    fn run(&mut self, nat: &mut dyn NatPolicy) -> i64 {
+        let mut idle = 0;
+        loop {
+            self.round += 1;
+            let mut busy = false;
+
+            for i in 0..self.nodes.len() {
+                let mut input: Vec<i64> = self.queues[i].drain(..).collect();
+                if input.is_empty() {
+                    input.push(-1);
+                } else {
+                    busy = true;
+                }
+                self.nodes[i].run(&mut input);
+
+                let output: Vec<i64> = self.nodes[i].output.drain(..).collect();
+                for p in output.chunks(3) {
+                    busy = true;
+                    let (to, x, y) = (p[0] as usize, p[1], p[2]);
+                    self.send(i, to, x, y);
+                    if to == NAT {
+                        match nat.receive(x, y) {
+                            NatAction::Stop(answer) => return answer,
+                            NatAction::Send(x, y) => self.send(NAT, 0, x, y),
+                            NatAction::Wait => (),
+                        }
+                    }
                 }
-                queue.push(cpu.output[oi + 1]);
-                queue.push(cpu.output[oi + 2]);
-            }
-            cpu.output.clear();
-
-            let queue = queues.entry(i).or_insert(Vec::new());
-            if queue.len() > 0 {
-                cpu.run(queue);
-                all_idle = false;
-            } else {
-                cpu.run(&mut vec![-1]);
             }
-        }
 
-        if all_idle {
-            match queues.get_mut(&255) {
-                Some(nat) if nat.len() > 0 => {
-                    let y = nat[1];
-                    if !nat_vals.insert(y) {
-                        return y;
+            idle = if busy { 0 } else { idle + 1 };
+            if idle >= self.options.idle_rounds {
+                match nat.idle() {
+                    NatAction::Stop(answer) => return answer,
+                    NatAction::Send(x, y) => {
+                        self.send(NAT, 0, x, y);
+                        idle = 0;
                     }
-                    cpus[0].run(nat);
+                    NatAction::Wait => (),
                 }
-                _ => (),
             }
         }
     }
+
+    fn log_text(&self) -> String {
+        self.log
+            .iter()
+            .map(|p| {
+                format!(
+                    "round {}: {} -> {} x={} y={}\n",
+                    p.round, p.from, p.to, p.x, p.y
+                )
+            })
+            .collect()
+    }
+
+    fn log_json(&self) -> String {
+        let packets: Vec<String> = self
+            .log
+            .iter()
+            .map(|p| {
+                format!(
+                    "  {{\"round\": {}, \"from\": {}, \"to\": {}, \"x\": {}, \"y\": {}}}",
+                    p.round, p.from, p.to, p.x, p.y
+                )
+            })
+            .collect();
+        format!("[\n{}\n]\n", packets.join(",\n"))
+    }
+}
+
+fn run_1(program: &str) -> i64 {
+    Network::new(program, NetworkOptions::default()).run(&mut FirstPacket)
+}
+
+fn run_2(program: &str) -> i64 {
+    Network::new(program, NetworkOptions::default()).run(&mut WakeUp::default())
+}
+
+#[cfg(test)]
+mod tests {
+    // Sends (its address, 7) to the NAT once, then reads input forever
+    const SEND_ONCE: &str = "3,100,104,255,4,100,104,7,3,101,1105,1,8";
+
+    #[test]
+    fn aoc23_log() {
+        use super::*;
+        let options = NetworkOptions {
+            nodes: 3,
+            idle_rounds: 1,
+        };
+        let mut network = Network::new(SEND_ONCE, options);
+        assert_eq!(network.run(&mut FirstPacket), 7);
+        assert_eq!(network.log_text(), "round 1: 0 -> 255 x=0 y=7\n");
+        assert_eq!(
+            network.log_json(),
+            "[\n  {\"round\": 1, \"from\": 0, \"to\": 255, \"x\": 0, \"y\": 7}\n]\n"
+        );
+    }
+
+    #[test]
+    fn aoc23_wake_up() {
+        use super::*;
+        let options = NetworkOptions {
+            nodes: 3,
+            idle_rounds: 1,
+        };
+        let mut network = Network::new(SEND_ONCE, options);
+        assert_eq!(network.run(&mut WakeUp::default()), 7);
+        assert_eq!(
+            network.log_text(),
+            "round 1: 0 -> 255 x=0 y=7
+round 1: 1 -> 255 x=1 y=7
+round 1: 2 -> 255 x=2 y=7
+round 2: 255 -> 0 x=2 y=7
+"
+        );
+    }
+
+    #[test]
+    fn aoc23_run() {
+        use super::*;
+        let input = fs::read_to_string("day23.txt").unwrap();
+        assert_eq!(run_1(&input), 19530);
+        assert_eq!(run_2(&input), 12725);
+    }
 }
@@ -1,10 +1,44 @@
-use std::collections::HashSet;
+use crate::life::{Edges, Grid, Life, Neighborhood, Rule};
+use crate::render::{Cell, Renderer, Terminal};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::time::Instant;
 
 pub fn run() {
     let input = fs::read_to_string("day24.txt").unwrap();
     println!("24:1 {}", run_1(&input));
-    println!("24:2 {}", run_2(&input));
+    println!("24:2 {}", run_2(&input, 200));
+
+    // cargo run 24 levels [minutes]|bench|life [rule] [4|8] [bounded|wrapping]
+    let mut args = std::env::args().skip(2);
+    match args.next().as_deref() {
+        Some("levels") => {
+            let minutes = args.next().map_or(10, |m| m.parse().unwrap());
+            print_levels(&run_recursive(&input, minutes));
+        }
+        Some("bench") => bench(&input),
+        Some("life") => {
+            let mut life = eris();
+            if let Some(rule) = args.next() {
+                life.rule = Rule::parse(&rule).unwrap().1;
+            }
+            life.neighborhood = match args.next().as_deref() {
+                Some("8") => Neighborhood::Moore,
+                _ => Neighborhood::VonNeumann,
+            };
+            life.edges = match args.next().as_deref() {
+                Some("wrapping") => Edges::Wrapping,
+                _ => Edges::Bounded,
+            };
+            let cycle = life.find_cycle(Grid::parse(&input));
+            println!(
+                "{} repeats every {} minutes from minute {}:",
+                life.rule, cycle.length, cycle.start
+            );
+            print!("{}", cycle.grid);
+        }
+        _ => (),
+    }
 }
 
 fn parse(input: &str) -> (HashSet<(usize, usize)>, usize) {
@@ -42,7 +76,7 @@ fn num_nbr_bugs(bugs: &HashSet<(usize, usize)>, x: usize, y: usize) -> usize {
     num_bugs
 }
 
-fn run_1(input: &str) -> u128 {
+fn run_1_set(input: &str) -> u128 {
     let (mut bugs, size) = parse(input);
 
     let mut old_bugs = HashSet::new();
@@ -75,63 +109,302 @@ fn run_1(input: &str) -> u128 {
     }
 }
 
-fn run_2(input: &str) -> u128 {
-    let (mut bugs, size) = parse(input);
+// Day 24 as a life-like automaton, the bitboards below run its rule
+fn eris() -> Life {
+    Life {
+        rule: Rule::parse("B12/S1").unwrap().1,
+        neighborhood: Neighborhood::VonNeumann,
+        edges: Edges::Bounded,
+    }
+}
 
-    let mut old_bugs = HashSet::new();
+fn biodiversity(grid: &Grid) -> u64 {
+    assert!(
+        grid.width * grid.height <= 64,
+        "too many tiles for a rating"
+    );
+    grid.alive().map(|(x, y)| 1 << (y * grid.width + x)).sum()
+}
 
-    loop {
-        println!("bugs:");
-        for y in 0..size {
-            for x in 0..size {
-                match bugs.contains(&(x, y)) {
-                    true => print!("#"),
-                    false => print!("."),
-                }
+fn run_1_life(input: &str) -> u64 {
+    biodiversity(&eris().find_cycle(Grid::parse(input)).grid)
+}
+
+fn draw_bugs(bugs: &HashSet<(usize, usize)>, size: usize, renderer: &mut dyn Renderer) {
+    for y in 0..size {
+        for x in 0..size {
+            match bugs.contains(&(x, y)) {
+                true => renderer.set(x, y, Cell::new('#', 0x00ff00)),
+                false => renderer.set(x, y, Cell::new('.', 0x404040)),
             }
-            println!("");
         }
-        println!("");
+    }
+}
 
-        let mut bio_div_rating = 0;
-        let mut new_bugs = HashSet::new();
-        for y in 0..size {
-            for x in 0..size {
-                let nbrs = num_nbr_bugs(&bugs, x, y);
-                if bugs.contains(&(x, y)) && nbrs == 1 {
-                    new_bugs.insert((x, y));
-                } else if !bugs.contains(&(x, y)) && (nbrs == 1 || nbrs == 2) {
-                    new_bugs.insert((x, y));
-                }
-                if new_bugs.contains(&(x, y)) {
-                    let idx = y * size + x;
-                    println!("{:?} -> {}", (x, y), idx);
-                    let bdr = 2u128.pow(idx as u32);
-                    bio_div_rating += bdr;
-                }
+fn print_bugs(bugs: &HashSet<(usize, usize)>, size: usize) {
+    let mut terminal = Terminal::plain(size, size);
+    draw_bugs(bugs, size, &mut terminal);
+    terminal.present();
+}
+
+// A tile on some level of the recursive grids, level + 1 is the grid inside
+// the centre tile and level - 1 the one around it
+type Tile = (i32, usize, usize);
+
+const SIZE: usize = 5;
+const CENTER: usize = SIZE / 2;
+
+fn recursive_neighbors((level, x, y): Tile) -> Vec<Tile> {
+    let mut res = Vec::new();
+    for (dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+        if nx < 0 {
+            res.push((level - 1, CENTER - 1, CENTER));
+        } else if nx >= SIZE as i32 {
+            res.push((level - 1, CENTER + 1, CENTER));
+        } else if ny < 0 {
+            res.push((level - 1, CENTER, CENTER - 1));
+        } else if ny >= SIZE as i32 {
+            res.push((level - 1, CENTER, CENTER + 1));
+        } else if (nx as usize, ny as usize) == (CENTER, CENTER) {
+            // The whole edge of the inner grid facing this tile
+            for i in 0..SIZE {
+                res.push(match (dx, dy) {
+                    (1, 0) => (level + 1, 0, i),
+                    (-1, 0) => (level + 1, SIZE - 1, i),
+                    (0, 1) => (level + 1, i, 0),
+                    _ => (level + 1, i, SIZE - 1),
+                });
             }
+        } else {
+            res.push((level, nx as usize, ny as usize));
         }
+    }
+    res
+}
 
-        bugs = new_bugs;
-        if old_bugs.contains(&bio_div_rating) {
-            println!("bugs:");
-            for y in 0..size {
-                for x in 0..size {
-                    match bugs.contains(&(x, y)) {
-                        true => print!("#"),
-                        false => print!("."),
-                    }
-                }
-                println!("");
-            }
-            println!("");
-            return bio_div_rating;
+// A bug survives with exactly one neighbour and an empty tile gets a bug
+// with one or two
+fn step_recursive(bugs: &HashSet<Tile>) -> HashSet<Tile> {
+    let mut counts: HashMap<Tile, usize> = HashMap::new();
+    for bug in bugs.iter() {
+        for n in recursive_neighbors(*bug) {
+            *counts.entry(n).or_insert(0) += 1;
         }
+    }
 
-        old_bugs.insert(bio_div_rating);
+    counts
+        .into_iter()
+        .filter(|(tile, n)| *n == 1 || (*n == 2 && !bugs.contains(tile)))
+        .map(|(tile, _)| tile)
+        .collect()
+}
+
+fn run_recursive(input: &str, minutes: usize) -> HashSet<Tile> {
+    let (bugs, _) = parse(input);
+    let mut bugs: HashSet<Tile> = bugs.into_iter().map(|(x, y)| (0, x, y)).collect();
+    for _ in 0..minutes {
+        bugs = step_recursive(&bugs);
     }
+    bugs
+}
+
+fn print_levels(bugs: &HashSet<Tile>) {
+    let mut levels: Vec<i32> = bugs.iter().map(|(level, _, _)| *level).collect();
+    levels.sort_unstable();
+    levels.dedup();
+
+    for level in levels {
+        println!("Depth {}:", level);
+        let grid = bugs
+            .iter()
+            .filter(|(l, _, _)| *l == level)
+            .map(|(_, x, y)| (*x, *y))
+            .collect();
+        print_bugs(&grid, SIZE);
+        println!();
+    }
+}
+
+fn run_2_set(input: &str, minutes: usize) -> usize {
+    run_recursive(input, minutes).len()
 }
 
+// A 5x5 grid in the low 25 bits, the tile at (x, y) is bit y * 5 + x. That
+// makes the value of a board its biodiversity rating.
+type Board = u32;
+
+const FULL: Board = (1 << 25) - 1;
+const TOP_ROW: Board = 0b11111;
+const BOTTOM_ROW: Board = TOP_ROW << 20;
+const LEFT_COL: Board = 0b00001_00001_00001_00001_00001;
+const RIGHT_COL: Board = LEFT_COL << 4;
+
+fn bit(x: usize, y: usize) -> Board {
+    1 << (y * SIZE + x)
+}
+
+fn to_board(bugs: &HashSet<(usize, usize)>) -> Board {
+    bugs.iter().fold(0, |b, (x, y)| b | bit(*x, *y))
+}
+
+// Neighbour counts for all tiles at once, as bit slices. Bit i of the count
+// of a tile is in `bits[i]`, enough for the most neighbours a tile can have.
+#[derive(Default)]
+struct Counter {
+    bits: [Board; 4],
+}
+
+impl Counter {
+    // Add one to the count of every tile in `n`
+    fn add(&mut self, n: Board) {
+        let mut carry = n;
+        for b in self.bits.iter_mut() {
+            let next = *b & carry;
+            *b ^= carry;
+            carry = next;
+        }
+    }
+
+    // The tiles with exactly `n` neighbours
+    fn count(&self, n: usize) -> Board {
+        self.bits
+            .iter()
+            .enumerate()
+            .fold(FULL, |res, (i, b)| match n >> i & 1 {
+                1 => res & b,
+                _ => res & !b,
+            })
+    }
+
+    // Adds the four neighbours in the same grid
+    fn add_flat(&mut self, board: Board) {
+        self.add((board << 1) & !LEFT_COL & FULL);
+        self.add((board >> 1) & !RIGHT_COL);
+        self.add((board << 5) & FULL);
+        self.add(board >> 5);
+    }
+
+    fn next(&self, board: Board, rule: &Rule) -> Board {
+        (0..=8).fold(0, |res, n| {
+            let tiles = self.count(n);
+            let survive = if rule.next(true, n) { board & tiles } else { 0 };
+            let birth = if rule.next(false, n) {
+                !board & tiles
+            } else {
+                0
+            };
+            res | survive | birth
+        }) & FULL
+    }
+}
+
+// The bitboards only count the four neighbours in a bounded grid, so of a
+// life-like automaton only the rule can differ from day 24
+fn board_rule(life: &Life) -> Rule {
+    assert!(
+        life.neighborhood == Neighborhood::VonNeumann && life.edges == Edges::Bounded,
+        "bitboards only run bounded grids with four neighbours"
+    );
+    life.rule
+}
+
+fn step_board(board: Board, rule: &Rule) -> Board {
+    let mut counter = Counter::default();
+    counter.add_flat(board);
+    counter.next(board, rule)
+}
+
+fn run_1(input: &str) -> Board {
+    let rule = board_rule(&eris());
+    let mut board = to_board(&parse(input).0);
+    let mut seen = HashSet::new();
+    while seen.insert(board) {
+        board = step_board(board, &rule);
+    }
+    board
+}
+
+const CENTER_BIT: Board = 1 << 12;
+
+// The next state of a level given the levels around and inside it
+fn step_level(outer: Board, board: Board, inner: Board, rule: &Rule) -> Board {
+    let mut counter = Counter::default();
+    counter.add_flat(board);
+
+    // The tiles next to the centre of the outer grid border whole edges
+    let edges = [
+        (bit(CENTER, CENTER - 1), TOP_ROW),
+        (bit(CENTER, CENTER + 1), BOTTOM_ROW),
+        (bit(CENTER - 1, CENTER), LEFT_COL),
+        (bit(CENTER + 1, CENTER), RIGHT_COL),
+    ];
+    for (tile, edge) in edges.iter() {
+        if outer & tile != 0 {
+            counter.add(*edge);
+        }
+        // Every bug on the inner edge counts for the tile next to the centre
+        for _ in 0..(inner & edge).count_ones() {
+            counter.add(*tile);
+        }
+    }
+
+    counter.next(board, rule) & !CENTER_BIT
+}
+
+// The levels from the outermost in, with room for one more level on each
+// side every minute
+fn step_levels(levels: &[Board], rule: &Rule) -> Vec<Board> {
+    let mut padded = vec![0, 0];
+    padded.extend(levels);
+    padded.extend(&[0, 0]);
+
+    let mut res: Vec<Board> = (1..padded.len() - 1)
+        .map(|i| step_level(padded[i - 1], padded[i], padded[i + 1], rule))
+        .collect();
+    while res.first() == Some(&0) {
+        res.remove(0);
+    }
+    while res.last() == Some(&0) {
+        res.pop();
+    }
+    res
+}
+
+fn run_2(input: &str, minutes: usize) -> usize {
+    let rule = board_rule(&eris());
+    let mut levels = vec![to_board(&parse(input).0)];
+    for _ in 0..minutes {
+        levels = step_levels(&levels, &rule);
+    }
+    levels.iter().map(|b| b.count_ones() as usize).sum()
+}
+
+// cargo run --release 24 bench
+fn bench(input: &str) {
+    let runs = 20;
+    let time = |name: &str, f: &dyn Fn() -> usize| {
+        let start = Instant::now();
+        let mut res = 0;
+        for _ in 0..runs {
+            res = f();
+        }
+        println!(
+            "{:<16} {:>12?} per run, {}",
+            name,
+            start.elapsed() / runs,
+            res
+        );
+    };
+
+    time("1 HashSet", &|| run_1_set(input) as usize);
+    time("1 life", &|| run_1_life(input) as usize);
+    time("1 bitboard", &|| run_1(input) as usize);
+    time("2 HashSet", &|| run_2_set(input, 200));
+    time("2 bitboard", &|| run_2(input, 200));
+}
+
+#[cfg(test)]
 mod tests {
     #[test]
     fn aoc24_parse() {
@@ -157,5 +430,146 @@ mod tests {
 ..#..
 #...";
         assert_eq!(super::run_1(state), 2129920);
+        assert_eq!(super::run_1_set(state), 2129920);
+        assert_eq!(super::run_1_life(state), 2129920);
+
+        let input = std::fs::read_to_string("day24.txt").unwrap();
+        assert_eq!(super::run_1_life(&input), super::run_1(&input) as u64);
+    }
+
+    #[test]
+    fn aoc24_bitboard() {
+        use super::*;
+        let state = "....#
+#..#.
+#..##
+..#..
+#....";
+        let (bugs, _) = parse(state);
+        let board = to_board(&bugs);
+        assert_eq!(board.count_ones() as usize, bugs.len());
+        assert_eq!(board & bit(4, 0), bit(4, 0));
+        assert_eq!(board & bit(0, 4), bit(0, 4));
+
+        let mut counter = Counter::default();
+        for n in 0..5 {
+            counter.add(if n % 2 == 0 { 0b111 } else { 0b101 });
+        }
+        // Counts 5, 3 and 5
+        assert_eq!(counter.bits, [0b111, 0b010, 0b101, 0]);
+        assert_eq!(counter.count(5), 0b101);
+        assert_eq!(counter.count(3), 0b010);
+        assert_eq!(counter.count(0), FULL & !0b111);
+
+        // Same as the HashSet version for a few minutes
+        let rule = board_rule(&eris());
+        let mut bugs = bugs;
+        let mut board = board;
+        for _ in 0..10 {
+            let next: HashSet<_> = (0..5)
+                .flat_map(|y| (0..5).map(move |x| (x, y)))
+                .filter(|(x, y)| {
+                    let n = num_nbr_bugs(&bugs, *x, *y);
+                    n == 1 || (n == 2 && !bugs.contains(&(*x, *y)))
+                })
+                .collect();
+            bugs = next;
+            board = step_board(board, &rule);
+            assert_eq!(board, to_board(&bugs));
+        }
+
+        // And as the life engine with other rules
+        for rule in ["B2/S23", "B134/S0", "B/S1234"].iter() {
+            let mut life = eris();
+            life.rule = Rule::parse(rule).unwrap().1;
+            let rule = board_rule(&life);
+            let mut grid = Grid::parse(state);
+            let mut board = to_board(&parse(state).0);
+            for _ in 0..10 {
+                grid = life.step(&grid);
+                board = step_board(board, &rule);
+                assert_eq!(board as u64, biodiversity(&grid));
+            }
+        }
+    }
+
+    #[test]
+    fn aoc24_biodiversity() {
+        use super::*;
+        assert_eq!(biodiversity(&Grid::parse("#.\n.#\n")), 9);
+        // The largest grid that still fits
+        let mut grid = Grid::new(8, 8);
+        grid.set(7, 7, true);
+        assert_eq!(biodiversity(&grid), 1 << 63);
+    }
+
+    #[test]
+    #[should_panic(expected = "too many tiles for a rating")]
+    fn aoc24_biodiversity_size() {
+        use super::*;
+        biodiversity(&Grid::new(8, 9));
+    }
+
+    #[test]
+    #[should_panic(expected = "bitboards only run bounded grids")]
+    fn aoc24_board_rule() {
+        use super::*;
+        let mut life = eris();
+        life.neighborhood = Neighborhood::Moore;
+        board_rule(&life);
+    }
+
+    #[test]
+    fn aoc24_neighbors() {
+        use super::*;
+        // The examples from the puzzle, with the tiles numbered 1-25
+        let neighbors = |tile: Tile| {
+            let mut res: Vec<(i32, usize)> = recursive_neighbors(tile)
+                .into_iter()
+                .map(|(l, x, y)| (l, y * 5 + x + 1))
+                .collect();
+            res.sort_unstable();
+            res
+        };
+        assert_eq!(
+            neighbors((0, 3, 3)),
+            vec![(0, 14), (0, 18), (0, 20), (0, 24)]
+        );
+        assert_eq!(neighbors((1, 1, 0)), vec![(0, 8), (1, 1), (1, 3), (1, 7)]);
+        assert_eq!(neighbors((1, 3, 0)), vec![(0, 8), (1, 3), (1, 5), (1, 9)]);
+        assert_eq!(neighbors((1, 4, 0)), vec![(0, 8), (0, 14), (1, 4), (1, 10)]);
+        assert_eq!(
+            neighbors((1, 3, 2)),
+            vec![
+                (1, 9),
+                (1, 15),
+                (1, 19),
+                (2, 5),
+                (2, 10),
+                (2, 15),
+                (2, 20),
+                (2, 25)
+            ]
+        );
+    }
+
+    #[test]
+    fn aoc24_run_2() {
+        use super::*;
+        let state = "....#
+#..#.
+#..##
+..#..
+#....";
+        assert_eq!(run_2(state, 10), 99);
+        assert_eq!(run_2_set(state, 10), 99);
+
+        let bugs = run_recursive(state, 10);
+        assert_eq!(bugs.iter().map(|(l, _, _)| *l).min().unwrap(), -5);
+        assert_eq!(bugs.iter().map(|(l, _, _)| *l).max().unwrap(), 5);
+
+        let input = fs::read_to_string("day24.txt").unwrap();
+        assert_eq!(run_2(&input, 200), 1872);
+        assert_eq!(run_2_set(&input, 200), 1872);
     }
 }
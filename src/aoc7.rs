@@ -1,4 +1,5 @@
-use permutohedron::Heap;
+use crate::intcode::{State, CPU};
+use itertools::Itertools;
 use rayon::prelude::*;
 use std::fs;
 
@@ -8,65 +9,115 @@ pub fn run() {
     println!("day7-2: {}", run_2(&input));
 }
 
-fn run_amp_1(program: &str, phases: &[i64]) -> i64 {
-    let mut io = 0;
-
-    for p in phases {
-        let mut amp = super::intcode::CPU::new(program);
-        let mut input = vec![*p, io];
-        amp.run(&mut input);
-        io = amp.output[0]
-    }
-    io
+// How the amplifiers are connected. Every amplifier gets its phase setting
+// first, then the initial signals and then the outputs of the amplifiers
+// connected to it, in the order they are produced.
+struct Topology {
+    nodes: usize,
+    // Every output of the first node is sent to the second
+    edges: Vec<(usize, usize)>,
+    // Signals sent to a node before the amplifiers start
+    signals: Vec<(usize, i64)>,
+    // The node whose last output is the result
+    result: usize,
 }
 
-pub fn run_1(input: &str) -> i64 {
-    let mut data = [0, 1, 2, 3, 4];
-    let heap = Heap::new(&mut data);
-    let phases = heap.collect::<Vec<_>>();
+impl Topology {
+    // Each node sends to the next, the last one gives the result
+    fn chain(nodes: usize) -> Self {
+        Topology {
+            nodes,
+            edges: (1..nodes).map(|i| (i - 1, i)).collect(),
+            signals: vec![(0, 0)],
+            result: nodes - 1,
+        }
+    }
 
-    phases
-        .par_iter()
-        .map(|p| run_amp_1(input, p))
-        .max()
-        .unwrap()
+    // A chain where the last node also sends back to the first
+    fn ring(nodes: usize) -> Self {
+        let mut topology = Self::chain(nodes);
+        topology.edges.push((nodes - 1, 0));
+        topology
+    }
 }
 
-fn run_amp_2(program: &str, phases: &[i64]) -> i64 {
-    let mut amps = (0..5)
-        .map(|_| super::intcode::CPU::new(program))
-        .collect::<Vec<_>>();
-    let mut inputs: Vec<Vec<i64>> = phases.iter().map(|p| vec![*p]).collect::<Vec<_>>();
-    // Initial input for amp A
-    inputs[0].push(0);
+// Runs every amplifier with input until all of them have exited or are
+// waiting for input nobody will send
+fn run_topology(program: &str, topology: &Topology, phases: &[i64]) -> i64 {
+    let mut amps: Vec<CPU> = (0..topology.nodes).map(|_| CPU::new(program)).collect();
+    let mut inputs: Vec<Vec<i64>> = phases.iter().map(|p| vec![*p]).collect();
+    for (node, signal) in topology.signals.iter() {
+        inputs[*node].push(*signal);
+    }
+    let mut exited = vec![false; topology.nodes];
+    let mut result = None;
 
     loop {
-        for (i, amp) in amps.iter_mut().enumerate() {
-            let ret = amp.run(&mut inputs[i]);
-            let il = inputs.len();
-            inputs[(i + 1) % il].push(amp.output[amp.output.len() - 1]);
-            if i == 4 && ret == super::intcode::State::Exited {
-                return amp.output[amp.output.len() - 1];
+        let mut progress = false;
+        for i in 0..topology.nodes {
+            if exited[i] || inputs[i].is_empty() {
+                continue;
+            }
+            progress = true;
+
+            let mut input = std::mem::take(&mut inputs[i]);
+            exited[i] = amps[i].run(&mut input) == State::Exited;
+
+            let output: Vec<i64> = amps[i].output.drain(..).collect();
+            if i == topology.result && !output.is_empty() {
+                result = output.last().copied();
+            }
+            for (_, to) in topology.edges.iter().filter(|(from, _)| *from == i) {
+                inputs[*to].extend(output.iter());
             }
         }
+
+        if !progress {
+            return result.unwrap();
+        }
     }
 }
 
-pub fn run_2(input: &str) -> i64 {
-    let mut data = [5, 6, 7, 8, 9];
-    let heap = Heap::new(&mut data);
-    let phases = heap.collect::<Vec<_>>();
+// The highest result and its phase settings, trying every way to give the
+// nodes different phase settings from `phase_set`. None when there are fewer
+// phase settings than nodes.
+fn best_phases(program: &str, topology: &Topology, phase_set: &[i64]) -> Option<(i64, Vec<i64>)> {
+    let phases: Vec<Vec<i64>> = phase_set
+        .iter()
+        .copied()
+        .permutations(topology.nodes)
+        .collect();
 
     phases
-        .par_iter()
-        .map(|p| run_amp_2(input, p))
+        .into_par_iter()
+        .map(|p| (run_topology(program, topology, &p), p))
         .max()
+}
+
+pub fn run_1(input: &str) -> i64 {
+    best_phases(input, &Topology::chain(5), &[0, 1, 2, 3, 4])
+        .unwrap()
+        .0
+}
+
+pub fn run_2(input: &str) -> i64 {
+    best_phases(input, &Topology::ring(5), &[5, 6, 7, 8, 9])
         .unwrap()
+        .0
 }
 
 #[cfg(test)]
 mod tests {
-    //
+    use super::*;
+
+    fn run_amp_1(program: &str, phases: &[i64]) -> i64 {
+        run_topology(program, &Topology::chain(phases.len()), phases)
+    }
+
+    fn run_amp_2(program: &str, phases: &[i64]) -> i64 {
+        run_topology(program, &Topology::ring(phases.len()), phases)
+    }
+
     #[test]
     fn aoc7_1() {
         use super::*;
@@ -96,6 +147,44 @@ mod tests {
         assert_eq!(18216, run_2(input));
     }
 
+    #[test]
+    fn aoc7_topology() {
+        use super::*;
+        // Outputs the sum of the phase setting and two signals
+        let sum = "3,20,3,21,3,22,1,20,21,23,1,23,22,23,4,23,99,0,0,0,0,0,0,0";
+
+        // 0 fans out to 1 and 2 which both send to 3
+        let diamond = Topology {
+            nodes: 4,
+            edges: vec![(0, 1), (0, 2), (1, 3), (2, 3)],
+            signals: vec![(0, 1), (0, 2), (1, 10), (2, 20)],
+            result: 3,
+        };
+        // 3 + (1 + 10 + 3) + (2 + 20 + 3)
+        assert_eq!(run_topology(sum, &diamond, &[0, 1, 2, 3]), 42);
+        // The phase set can be larger than the number of nodes
+        assert_eq!(
+            best_phases(sum, &diamond, &[0, 1, 2, 3, 10]),
+            Some((62, vec![10, 3, 2, 1]))
+        );
+        assert_eq!(best_phases(sum, &diamond, &[0, 1, 2]), None);
+
+        // Two separate feedback loops, the result comes from the second
+        let input =
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5";
+        let mut loops = Topology::ring(5);
+        loops.nodes = 10;
+        loops
+            .edges
+            .extend(loops.edges.clone().iter().map(|(a, b)| (a + 5, b + 5)));
+        loops.signals.push((5, 0));
+        loops.result = 9;
+        assert_eq!(
+            run_topology(input, &loops, &[9, 8, 7, 6, 5, 9, 8, 7, 6, 5]),
+            139629729
+        );
+    }
+
     #[test]
     fn aoc7_still_correct() {
         use super::*;
@@ -1,11 +1,24 @@
+use crate::render::{write_pbm, write_png};
 use nom::{bytes::complete::take_while1, character::is_digit, combinator::map, IResult};
+use std::fmt;
 use std::fs;
 
 pub fn run() {
     let input = fs::read_to_string("day8.txt").unwrap();
     // 1848 too low
     println!("day8-1: {}", run_1(&input, 25, 6));
-    println!("day8-2:\n{}", run_2(&input, 25, 6));
+    let image = run_2(&input, 25, 6);
+    match crate::ocr::recognize(&image) {
+        Ok(text) => println!("day8-2: {}", text),
+        Err(e) => println!("day8-2:\n{}\n{}", image, e),
+    }
+
+    // cargo run 8 export
+    if std::env::args().nth(2).as_deref() == Some("export") {
+        let image = SpaceImage::new(&input, 25, 6).unwrap();
+        image.write_pbm("day8.pbm").unwrap();
+        image.write_png("day8.png", 8).unwrap();
+    }
 }
 
 fn is_char_digit(chr: char) -> bool {
@@ -18,45 +31,140 @@ fn parse(i: &str) -> IResult<&str, Vec<u32>> {
     })(i)
 }
 
-fn run_1(input: &str, width: usize, height: usize) -> u32 {
-    let (_, image) = parse(input).unwrap();
-
-    let min_zero_layer = image
-        .chunks(width * height)
-        .min_by_key(|layer| layer.iter().filter(|x| **x == 0).count())
-        .unwrap();
-    let (ones, twos) = min_zero_layer.iter().fold((0, 0), |(one_sum, two_sum), d| {
-        (
-            one_sum + if *d == 1 { 1 } else { 0 },
-            two_sum + if *d == 2 { 1 } else { 0 },
-        )
-    });
-    ones * twos
+const BLACK: u32 = 0;
+const WHITE: u32 = 1;
+const TRANSPARENT: u32 = 2;
+
+#[derive(Debug, PartialEq)]
+enum ImageError {
+    EmptyLayer,
+    NotDigits(String),
+    Length { len: usize, layer_size: usize },
 }
 
-fn run_2(input: &str, width: usize, height: usize) -> String {
-    let (_, image) = parse(input).unwrap();
-    let layers = image.chunks(width * height).collect::<Vec<_>>();
-
-    let mut res = String::new();
-
-    for row in 0..height {
-        for col in 0..width {
-            for l in layers.iter() {
-                let color = l[row * width + col];
-                if color == 0 {
-                    res += " ";
-                    break;
-                } else if color == 1 {
-                    res += "*";
-                    break;
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EmptyLayer => write!(f, "width and height must be larger than 0"),
+            Self::NotDigits(rest) => write!(f, "not a digit at: {}", rest),
+            Self::Length { len, layer_size } => write!(
+                f,
+                "{} digits is not a multiple of the layer size {}",
+                len, layer_size
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SpaceImage {
+    width: usize,
+    height: usize,
+    data: Vec<u32>,
+}
+
+impl SpaceImage {
+    fn new(input: &str, width: usize, height: usize) -> Result<Self, ImageError> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::EmptyLayer);
+        }
+
+        let (rest, data) = parse(input).unwrap_or((input, Vec::new()));
+        if !rest.trim().is_empty() {
+            return Err(ImageError::NotDigits(rest.to_string()));
+        }
+
+        let layer_size = width * height;
+        if data.is_empty() || data.len() % layer_size != 0 {
+            return Err(ImageError::Length {
+                len: data.len(),
+                layer_size,
+            });
+        }
+
+        Ok(SpaceImage {
+            width,
+            height,
+            data,
+        })
+    }
+
+    fn layers(&self) -> std::slice::Chunks<'_, u32> {
+        self.data.chunks(self.width * self.height)
+    }
+
+    // Number of each digit, 0-9, in every layer
+    fn histograms(&self) -> Vec<[usize; 10]> {
+        self.layers()
+            .map(|layer| {
+                let mut h = [0; 10];
+                for d in layer {
+                    h[*d as usize] += 1;
+                }
+                h
+            })
+            .collect()
+    }
+
+    // The first layer in front, a pixel is the first one that is not
+    // transparent going backwards through the layers
+    fn composite(&self) -> Vec<u32> {
+        let mut res = vec![TRANSPARENT; self.width * self.height];
+        for layer in self.layers().rev() {
+            for (r, p) in res.iter_mut().zip(layer) {
+                if *p != TRANSPARENT {
+                    *r = *p;
                 }
             }
         }
-        res += "\n";
+        res
+    }
+
+    fn render(&self) -> String {
+        let mut res = String::new();
+        for row in self.composite().chunks(self.width) {
+            for p in row {
+                res += if *p == WHITE { "*" } else { " " };
+            }
+            res += "\n";
+        }
+        res
+    }
+
+    // White pixels as lit
+    fn lit(&self) -> Vec<bool> {
+        self.composite().iter().map(|p| *p == WHITE).collect()
+    }
+
+    fn write_pbm(&self, path: &str) -> std::io::Result<()> {
+        write_pbm(path, self.width, self.height, &self.lit())
     }
 
-    res
+    fn write_png(&self, path: &str, scale: usize) -> std::io::Result<()> {
+        write_png(path, self.width, self.height, &self.lit(), scale)
+    }
+}
+
+// Encodes the image back to its digits
+impl fmt::Display for SpaceImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for d in &self.data {
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
+
+fn run_1(input: &str, width: usize, height: usize) -> usize {
+    let image = SpaceImage::new(input, width, height).unwrap();
+
+    let h = image.histograms();
+    let min_zero_layer = h.iter().min_by_key(|h| h[BLACK as usize]).unwrap();
+    min_zero_layer[1] * min_zero_layer[2]
+}
+
+fn run_2(input: &str, width: usize, height: usize) -> String {
+    SpaceImage::new(input, width, height).unwrap().render()
 }
 
 #[cfg(test)]
@@ -72,4 +180,35 @@ mod tests {
     fn aoc8_run_2() {
         assert_eq!(run_2("0222112222120000", 2, 2), " *\n* \n");
     }
+
+    #[test]
+    fn aoc8_image() {
+        let image = SpaceImage::new("0222112222120000\n", 2, 2).unwrap();
+        assert_eq!(image.layers().count(), 4);
+        assert_eq!(image.histograms()[0], [1, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(image.composite(), vec![0, 1, 1, 0]);
+        assert_eq!(image.lit(), vec![false, true, true, false]);
+        assert_eq!(image.to_string(), "0222112222120000");
+        assert_eq!(SpaceImage::new(&image.to_string(), 2, 2), Ok(image));
+
+        assert_eq!(
+            SpaceImage::new("12345", 2, 2),
+            Err(ImageError::Length {
+                len: 5,
+                layer_size: 4
+            })
+        );
+        assert_eq!(
+            SpaceImage::new("1234x", 2, 2),
+            Err(ImageError::NotDigits("x".to_string()))
+        );
+        assert_eq!(SpaceImage::new("1234", 0, 2), Err(ImageError::EmptyLayer));
+    }
+
+    #[test]
+    fn aoc8_ocr() {
+        let input = fs::read_to_string("day8.txt").unwrap();
+        let image = run_2(&input, 25, 6);
+        assert_eq!(crate::ocr::recognize(&image), Ok("AZCJC".to_string()));
+    }
 }
@@ -0,0 +1,299 @@
+use nom::bytes::complete::{tag, take_while};
+use nom::sequence::{preceded, separated_pair};
+use nom::IResult;
+use std::collections::HashMap;
+use std::fmt;
+
+// Life-like cellular automata on a rectangular grid, where the next state of
+// a cell only depends on whether it is alive and how many of its neighbours
+// are.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Neighborhood {
+    // The four orthogonal neighbours
+    VonNeumann,
+    // Also the four diagonal ones
+    Moore,
+}
+
+impl Neighborhood {
+    fn offsets(self) -> &'static [(i32, i32)] {
+        match self {
+            Neighborhood::VonNeumann => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Neighborhood::Moore => &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edges {
+    // Everything outside of the grid is dead
+    Bounded,
+    // The grid is a torus
+    Wrapping,
+}
+
+// Which neighbour counts give birth to a dead cell and which keep a live
+// cell alive, written as B/S like "B3/S23" for Conway's Game of Life
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    birth: [bool; 9],
+    survive: [bool; 9],
+}
+
+fn counts(digits: &str) -> [bool; 9] {
+    let mut res = [false; 9];
+    for d in digits.chars() {
+        res[d.to_digit(10).unwrap() as usize] = true;
+    }
+    res
+}
+
+impl Rule {
+    pub fn parse(i: &str) -> IResult<&str, Self> {
+        let digits = || take_while(|c: char| ('0'..='8').contains(&c));
+        let (i, (birth, survive)) = separated_pair(
+            preceded(tag("B"), digits()),
+            tag("/"),
+            preceded(tag("S"), digits()),
+        )(i)?;
+        Ok((
+            i,
+            Rule {
+                birth: counts(birth),
+                survive: counts(survive),
+            },
+        ))
+    }
+
+    pub fn next(&self, alive: bool, neighbors: usize) -> bool {
+        match alive {
+            true => self.survive[neighbors],
+            false => self.birth[neighbors],
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = |counts: &[bool; 9]| -> String {
+            (0..9)
+                .filter(|n| counts[*n])
+                .map(|n| std::char::from_digit(n as u32, 10).unwrap())
+                .collect()
+        };
+        write!(f, "B{}/S{}", digits(&self.birth), digits(&self.survive))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<bool>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(width > 0 && height > 0, "empty grid");
+        Grid {
+            width,
+            height,
+            cells: vec![false; width * height],
+        }
+    }
+
+    // '#' is alive, anything else dead. Short rows are padded with dead cells.
+    pub fn parse(input: &str) -> Self {
+        let rows: Vec<&str> = input.lines().filter(|l| !l.is_empty()).collect();
+        let width = rows.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+        let mut grid = Grid::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid.set(x, y, c == '#');
+            }
+        }
+        grid
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, alive: bool) {
+        self.cells[y * self.width + x] = alive;
+    }
+
+    // The live cells in reading order
+    pub fn alive(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, alive)| **alive)
+            .map(move |(i, _)| (i % width, i / width))
+    }
+}
+
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write!(f, "{}", if self.get(x, y) { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cycle {
+    // Generation the cycle starts at, the initial grid is generation 0
+    pub start: usize,
+    pub length: usize,
+    // The first grid that is seen twice
+    pub grid: Grid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Life {
+    pub rule: Rule,
+    pub neighborhood: Neighborhood,
+    pub edges: Edges,
+}
+
+impl Life {
+    pub fn neighbors(&self, grid: &Grid, x: usize, y: usize) -> usize {
+        let (w, h) = (grid.width as i32, grid.height as i32);
+        self.neighborhood
+            .offsets()
+            .iter()
+            .filter_map(|(dx, dy)| {
+                let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                match self.edges {
+                    Edges::Wrapping => Some((nx.rem_euclid(w), ny.rem_euclid(h))),
+                    Edges::Bounded if nx < 0 || ny < 0 || nx >= w || ny >= h => None,
+                    Edges::Bounded => Some((nx, ny)),
+                }
+            })
+            .filter(|(nx, ny)| grid.get(*nx as usize, *ny as usize))
+            .count()
+    }
+
+    pub fn step(&self, grid: &Grid) -> Grid {
+        let mut next = Grid::new(grid.width, grid.height);
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let alive = self.rule.next(grid.get(x, y), self.neighbors(grid, x, y));
+                next.set(x, y, alive);
+            }
+        }
+        next
+    }
+
+    // Steps until a grid shows up for the second time. There are only
+    // finitely many grids of a size, so this always ends.
+    pub fn find_cycle(&self, grid: Grid) -> Cycle {
+        let mut seen = HashMap::new();
+        let mut grid = grid;
+        let mut generation = 0;
+        loop {
+            if let Some(start) = seen.get(&grid) {
+                return Cycle {
+                    start: *start,
+                    length: generation - start,
+                    grid,
+                };
+            }
+            let next = self.step(&grid);
+            seen.insert(grid, generation);
+            grid = next;
+            generation += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conway(edges: Edges) -> Life {
+        Life {
+            rule: Rule::parse("B3/S23").unwrap().1,
+            neighborhood: Neighborhood::Moore,
+            edges,
+        }
+    }
+
+    #[test]
+    fn life_rule() {
+        let (rest, rule) = Rule::parse("B12/S1 ").unwrap();
+        assert_eq!(rest, " ");
+        assert!(rule.next(false, 1) && rule.next(false, 2) && !rule.next(false, 3));
+        assert!(rule.next(true, 1) && !rule.next(true, 2) && !rule.next(true, 0));
+        assert_eq!(rule.to_string(), "B12/S1");
+
+        assert_eq!(
+            Rule::parse("B/S012345678").unwrap().1.to_string(),
+            "B/S012345678"
+        );
+        assert!(Rule::parse("S23/B3").is_err());
+    }
+
+    #[test]
+    fn life_grid() {
+        let grid = Grid::parse("..#\n#\n");
+        assert_eq!((grid.width, grid.height), (3, 2));
+        assert_eq!(grid.alive().collect::<Vec<_>>(), vec![(2, 0), (0, 1)]);
+        assert_eq!(grid.to_string(), "..#\n#..\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "empty grid")]
+    fn life_empty_grid() {
+        Grid::new(3, 0);
+    }
+
+    #[test]
+    fn life_neighbors() {
+        let grid = Grid::parse("#.#\n...\n#.#\n");
+        let mut life = conway(Edges::Bounded);
+        assert_eq!(life.neighbors(&grid, 1, 1), 4);
+        assert_eq!(life.neighbors(&grid, 0, 0), 0);
+        life.edges = Edges::Wrapping;
+        assert_eq!(life.neighbors(&grid, 0, 0), 3);
+        life.neighborhood = Neighborhood::VonNeumann;
+        assert_eq!(life.neighbors(&grid, 1, 1), 0);
+        assert_eq!(life.neighbors(&grid, 0, 0), 2);
+    }
+
+    #[test]
+    fn life_cycle() {
+        // A blinker flips between two grids from the start
+        let blinker = Grid::parse(".....\n..#..\n..#..\n..#..\n.....\n");
+        let cycle = conway(Edges::Bounded).find_cycle(blinker.clone());
+        assert_eq!((cycle.start, cycle.length), (0, 2));
+        assert_eq!(cycle.grid, blinker);
+
+        // A glider on a torus is back where it started after moving one cell
+        // diagonally every four generations
+        let glider = Grid::parse(".#....\n..#...\n###...\n......\n......\n......\n");
+        let cycle = conway(Edges::Wrapping).find_cycle(glider.clone());
+        assert_eq!((cycle.start, cycle.length), (0, 24));
+
+        // Without wrapping it turns into a block in the corner
+        let cycle = conway(Edges::Bounded).find_cycle(glider);
+        assert_eq!(cycle.length, 1);
+        assert_eq!(cycle.grid.alive().count(), 4);
+    }
+}
@@ -27,6 +27,9 @@ mod aoc8;
 mod aoc9;
 mod helper;
 mod intcode;
+mod life;
+mod ocr;
+mod render;
 
 fn main() {
     let mut a = env::args();
@@ -0,0 +1,180 @@
+use std::fmt;
+
+// The block letters used in the puzzle answers, '#' is lit
+const SMALL: [(char, [&str; 6]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[rustfmt::skip]
+const LARGE: [(char, [&str; 10]); 15] = [
+    ('A', ["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"]),
+    ('B', ["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."]),
+    ('C', [".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."]),
+    ('E', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('F', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('G', [".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+    ('H', ["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"]),
+    ('J', ["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+    ('K', ["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+    ('L', ["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('N', ["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+    ('P', ["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('R', ["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+    ('X', ["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+    ('Z', ["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"]),
+];
+
+#[derive(Debug, PartialEq)]
+pub struct OcrError {
+    // What could be read, unknown glyphs are shown as '?'
+    pub text: String,
+    // The unknown glyphs, one string per row with '#' for lit pixels
+    pub unknown: Vec<Vec<String>>,
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Unknown glyphs in {}:", self.text)?;
+        for g in &self.unknown {
+            writeln!(f)?;
+            for row in g {
+                writeln!(f, "{}", row)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lookup(glyph: &[String]) -> Option<char> {
+    let matches = |pattern: &[&str]| glyph.iter().eq(pattern.iter());
+
+    SMALL
+        .iter()
+        .find(|(_, p)| matches(p))
+        .map(|(c, _)| *c)
+        .or_else(|| LARGE.iter().find(|(_, p)| matches(p)).map(|(c, _)| *c))
+}
+
+// Read the letters in a render where anything but ' ' and '.' is lit,
+// letters are separated by at least one empty column
+pub fn recognize(render: &str) -> Result<String, OcrError> {
+    let rows: Vec<Vec<bool>> = render
+        .lines()
+        .map(|l| l.chars().map(|c| c != ' ' && c != '.').collect())
+        .collect();
+
+    // Only keep the rows with the letters
+    let lit_row = |r: &Vec<bool>| r.iter().any(|p| *p);
+    let top = rows.iter().position(lit_row).unwrap_or(rows.len());
+    let bottom = rows.iter().rposition(lit_row).map_or(top, |b| b + 1);
+    let mut rows = rows[top..bottom].to_vec();
+
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    for r in rows.iter_mut() {
+        r.resize(width, false);
+    }
+    let empty_col = |x: usize| rows.iter().all(|r| !r[x]);
+
+    let mut text = String::new();
+    let mut unknown = Vec::new();
+    let mut x = 0;
+    while x < width {
+        if empty_col(x) {
+            x += 1;
+            continue;
+        }
+
+        let start = x;
+        while x < width && !empty_col(x) {
+            x += 1;
+        }
+
+        let glyph: Vec<String> = rows
+            .iter()
+            .map(|r| {
+                r[start..x]
+                    .iter()
+                    .map(|p| if *p { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        match lookup(&glyph) {
+            Some(c) => text.push(c),
+            None => {
+                text.push('?');
+                unknown.push(glyph);
+            }
+        }
+    }
+
+    if unknown.is_empty() {
+        Ok(text)
+    } else {
+        Err(OcrError { text, unknown })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ocr_small() {
+        let render = " **  ****  **    **  **
+*  *    * *  *    * *  *
+*  *   *  *       * *
+****  *   *       * *
+*  * *    *  * *  * *  *
+*  * ****  **   **   **
+";
+        assert_eq!(recognize(render), Ok("AZCJC".to_string()));
+    }
+
+    #[test]
+    fn ocr_large() {
+        let render = "#....#..######
+#....#..#.....
+.#..#...#.....
+.#..#...#.....
+..##....#####.
+..##....#.....
+.#..#...#.....
+.#..#...#.....
+#....#..#.....
+#....#..######";
+        assert_eq!(recognize(render), Ok("XE".to_string()));
+    }
+
+    #[test]
+    fn ocr_unknown() {
+        let render = ".##..#.#
+#..#.#.#
+#..#..#.
+####..#.
+#..#.#.#
+#..#.#.#";
+        let err = recognize(render).unwrap_err();
+        assert_eq!(err.text, "A?");
+        assert_eq!(
+            err.unknown,
+            vec![vec!["#.#", "#.#", ".#.", ".#.", "#.#", "#.#"]]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use minifb::{Window, WindowOptions};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Pixels per cell in windows and exported images
+pub const MULT: usize = 8;
+
+// Time between frames in animations
+pub const FRAME_DELAY: Duration = Duration::from_millis(20);
+
+// A grid cell, a window only uses the color and plain text only the glyph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub glyph: char,
+    pub color: u32,
+}
+
+impl Cell {
+    pub const fn new(glyph: char, color: u32) -> Self {
+        Cell { glyph, color }
+    }
+}
+
+pub const BLANK: Cell = Cell::new(' ', 0x00000000);
+
+pub trait Renderer {
+    // Cells outside of the grid are ignored
+    fn set(&mut self, x: usize, y: usize, cell: Cell);
+
+    // Show the cells drawn so far, returns false once the display is closed
+    fn present(&mut self) -> bool;
+}
+
+// Keeps the cells in memory, used by the other backends and in tests
+pub struct FrameBuffer {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    pub presented: usize,
+}
+
+impl FrameBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        FrameBuffer {
+            width,
+            height,
+            cells: vec![BLANK; width * height],
+            presented: 0,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+}
+
+impl fmt::Display for FrameBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                write!(f, "{}", self.get(x, y).glyph)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Renderer for FrameBuffer {
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = cell;
+        }
+    }
+
+    fn present(&mut self) -> bool {
+        self.presented += 1;
+        true
+    }
+}
+
+// Prints the glyphs to stdout, with ANSI colors redrawing in place or as
+// plain text one frame after the other
+pub struct Terminal {
+    fb: FrameBuffer,
+    ansi: bool,
+}
+
+impl Terminal {
+    pub fn new(width: usize, height: usize) -> Self {
+        Terminal {
+            fb: FrameBuffer::new(width, height),
+            ansi: true,
+        }
+    }
+
+    pub fn plain(width: usize, height: usize) -> Self {
+        Terminal {
+            fb: FrameBuffer::new(width, height),
+            ansi: false,
+        }
+    }
+}
+
+impl Renderer for Terminal {
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        self.fb.set(x, y, cell);
+    }
+
+    fn present(&mut self) -> bool {
+        if !self.ansi {
+            print!("{}", self.fb);
+            return self.fb.present();
+        }
+
+        let mut out = String::new();
+        if self.fb.presented == 0 {
+            // Clear the screen the first time
+            out += "\x1b[2J";
+        }
+        // Move the cursor to the top left corner
+        out += "\x1b[H";
+        for y in 0..self.fb.height {
+            for x in 0..self.fb.width {
+                let c = self.fb.get(x, y);
+                let (r, g, b) = (
+                    (c.color >> 16) & 0xff,
+                    (c.color >> 8) & 0xff,
+                    c.color & 0xff,
+                );
+                out += &format!("\x1b[38;2;{};{};{}m{}", r, g, b, c.glyph);
+            }
+            out += "\x1b[0m\n";
+        }
+        print!("{}", out);
+        self.fb.present()
+    }
+}
+
+// A minifb window where every cell is a `scale` x `scale` square
+pub struct WindowRenderer {
+    window: Window,
+    video: Vec<u32>,
+    width: usize,
+    height: usize,
+    scale: usize,
+}
+
+impl WindowRenderer {
+    pub fn new(title: &str, width: usize, height: usize, scale: usize) -> Self {
+        let window = Window::new(
+            title,
+            width * scale,
+            height * scale,
+            WindowOptions::default(),
+        )
+        .unwrap();
+
+        WindowRenderer {
+            window,
+            video: vec![0; width * scale * height * scale],
+            width,
+            height,
+            scale,
+        }
+    }
+}
+
+impl Renderer for WindowRenderer {
+    fn set(&mut self, x: usize, y: usize, cell: Cell) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        let row_len = self.width * self.scale;
+        for _y in (y * self.scale)..((y + 1) * self.scale) {
+            for _x in (x * self.scale)..((x + 1) * self.scale) {
+                self.video[_y * row_len + _x] = cell.color;
+            }
+        }
+    }
+
+    fn present(&mut self) -> bool {
+        self.window
+            .update_with_buffer(
+                &self.video,
+                self.width * self.scale,
+                self.height * self.scale,
+            )
+            .unwrap();
+        self.window.is_open()
+    }
+}
+
+// Black and white image as a plain PBM, `lit` pixels are black
+pub fn write_pbm(path: &str, width: usize, height: usize, lit: &[bool]) -> io::Result<()> {
+    let mut f = fs::File::create(path)?;
+    writeln!(f, "P1\n{} {}", width, height)?;
+    for row in lit.chunks(width) {
+        let row: Vec<&str> = row.iter().map(|p| if *p { "1" } else { "0" }).collect();
+        writeln!(f, "{}", row.join(" "))?;
+    }
+    Ok(())
+}
+
+// Image encoder errors as io errors
+pub fn to_io<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+// Grayscale PNG with black `lit` pixels, each one a `scale` x `scale` square
+pub fn write_png(
+    path: &str,
+    width: usize,
+    height: usize,
+    lit: &[bool],
+    scale: usize,
+) -> io::Result<()> {
+    let mut data = Vec::with_capacity(width * scale * height * scale);
+    for row in lit.chunks(width) {
+        for _ in 0..scale {
+            for p in row {
+                let v = if *p { 0x00 } else { 0xff };
+                data.extend(std::iter::repeat_n(v, scale));
+            }
+        }
+    }
+
+    let f = fs::File::create(path)?;
+    let mut encoder = png::Encoder::new(f, (width * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(to_io)?;
+    writer.write_image_data(&data).map_err(to_io)
+}
+
+// Binary PPM with the colors of the cells, each one a `scale` x `scale` square
+pub fn write_ppm(path: &str, fb: &FrameBuffer, scale: usize) -> io::Result<()> {
+    let mut f = fs::File::create(path)?;
+    write!(f, "P6\n{} {}\n255\n", fb.width * scale, fb.height * scale)?;
+
+    let mut data = Vec::with_capacity(fb.width * scale * fb.height * scale * 3);
+    for y in 0..fb.height {
+        for _ in 0..scale {
+            for x in 0..fb.width {
+                let c = fb.get(x, y).color;
+                let rgb = [(c >> 16) as u8, (c >> 8) as u8, c as u8];
+                for _ in 0..scale {
+                    data.extend_from_slice(&rgb);
+                }
+            }
+        }
+    }
+    f.write_all(&data)
+}
+
+// Writes frames one at a time to a directory as frame-0000.ppm,
+// frame-0001.ppm, ...
+pub struct FrameWriter {
+    dir: PathBuf,
+    scale: usize,
+    pub frames: usize,
+}
+
+impl FrameWriter {
+    pub fn new(dir: &str, scale: usize) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(FrameWriter {
+            dir: PathBuf::from(dir),
+            scale,
+            frames: 0,
+        })
+    }
+
+    pub fn write(&mut self, fb: &FrameBuffer) -> io::Result<()> {
+        let path = self.dir.join(format!("frame-{:04}.ppm", self.frames));
+        write_ppm(path.to_str().unwrap(), fb, self.scale)?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+// All the frames with a FrameWriter, returns how many were written
+pub fn write_frames(
+    dir: &str,
+    frames: impl IntoIterator<Item = FrameBuffer>,
+    scale: usize,
+) -> io::Result<usize> {
+    let mut writer = FrameWriter::new(dir, scale)?;
+    for fb in frames {
+        writer.write(&fb)?;
+    }
+    Ok(writer.frames)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn render_framebuffer() {
+        use super::*;
+        let mut fb = FrameBuffer::new(3, 2);
+        fb.set(0, 0, Cell::new('#', 0xffffff));
+        fb.set(2, 1, Cell::new('o', 0xff0000));
+        // Outside, ignored
+        fb.set(3, 1, Cell::new('x', 0));
+
+        assert_eq!(fb.get(2, 1), Cell::new('o', 0xff0000));
+        assert_eq!(fb.get(1, 1), BLANK);
+        assert_eq!(fb.to_string(), "#  \n  o\n");
+
+        assert!(fb.present());
+        assert_eq!(fb.presented, 1);
+    }
+
+    #[test]
+    fn render_export() {
+        use super::*;
+        let dir = std::env::temp_dir();
+        let lit = [true, false, false, true, true, true];
+
+        let pbm = dir.join("render_export.pbm");
+        write_pbm(pbm.to_str().unwrap(), 3, 2, &lit).unwrap();
+        assert_eq!(fs::read_to_string(pbm).unwrap(), "P1\n3 2\n1 0 0\n1 1 1\n");
+
+        let png = dir.join("render_export.png");
+        write_png(png.to_str().unwrap(), 3, 2, &lit, 2).unwrap();
+        assert!(fs::read(png).unwrap().starts_with(b"\x89PNG"));
+
+        let mut fb = FrameBuffer::new(2, 1);
+        fb.set(1, 0, Cell::new('#', 0x102030));
+        let ppm = dir.join("render_export.ppm");
+        write_ppm(ppm.to_str().unwrap(), &fb, 1).unwrap();
+        assert_eq!(
+            fs::read(ppm).unwrap(),
+            b"P6\n2 1\n255\n\x00\x00\x00\x10\x20\x30".to_vec()
+        );
+
+        let frames = dir.join("render_export");
+        let frames = frames.to_str().unwrap();
+        let written = write_frames(frames, vec![fb, FrameBuffer::new(2, 1)], 2).unwrap();
+        assert_eq!(written, 2);
+        let ppm = fs::read(std::path::Path::new(frames).join("frame-0001.ppm")).unwrap();
+        assert_eq!(ppm.len(), 11 + 4 * 2 * 3);
+    }
+}